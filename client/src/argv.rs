@@ -21,6 +21,11 @@ pub fn get_args() -> ArgMatches<'static> {
                 .help("The amount of shards to spawn. If omitted, the recommended amount of shards will be spawned.")
                 .value_name("COUNT")
             )
+            .arg(Arg::with_name("shards")
+                .long("shards")
+                .help("The IDs of the shards to spawn in this cluster, as a range (0..16) or a list (0,1,2). Requires the total shard count to be provided.")
+                .value_name("IDS")
+            )
             .arg(Arg::with_name("url")
                 .short("u")
                 .long("amqpurl")
@@ -27,6 +27,7 @@ pub enum Error {
     Timer(TimerError),
     Json(JsonError),
     InvalidFile,
+    InvalidShardIds,
 }
 
 impl Display for Error {
@@ -46,7 +47,8 @@ impl StdError for Error {
             Error::Gateway(e) => e.description(),
             Error::Json(e) => e.description(),
             Error::TomlDe(e) => e.description(),
-            Error::InvalidFile => "Invalid config file provided. Supported config files are JSON and TOML."
+            Error::InvalidFile => "Invalid config file provided. Supported config files are JSON and TOML.",
+            Error::InvalidShardIds => "Invalid shard IDs provided. Shard IDs must be a range (0..16) or a list (0,1,2), along with a total shard count."
         }
     }
 }
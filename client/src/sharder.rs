@@ -1,6 +1,7 @@
 use std::{
     env,
-    fs
+    fs,
+    result::Result as StdResult
 };
 use std::sync::Arc;
 
@@ -22,6 +23,7 @@ pub struct SpawnerOptions {
     amqp_subgroup: Option<String>,
     amqp_group: String,
     shard_count: Option<usize>,
+    shard_ids: Option<String>,
     token: String,
     config_path: Option<String>
 }
@@ -34,17 +36,13 @@ pub struct SpecGatewayMessage<'a> {
 }
 
 
-pub fn start_sharder(config: SpawnerOptions) -> impl Future<Item=(), Error=Error> {
+pub fn start_sharder(config: SpawnerOptions, strategy: ShardStrategy) -> impl Future<Item=(), Error=Error> {
     let amqp_url = config.amqp_url.clone();
     let group = config.amqp_group.clone();
     let subgroup = config.amqp_subgroup.clone();
     let token = config.token.clone();
-    let shard_count = match config.shard_count {
-        Some(r) => ShardStrategy::SpawnAmount(r),
-        None => ShardStrategy::Recommended
-    };
     let amqp = AmqpBroker::new(amqp_url, group, subgroup).from_err();
-    let sharder = ShardManager::new(token, shard_count).from_err();
+    let sharder = ShardManager::new(token, strategy).from_err();
 
     amqp.join(sharder).map(|(broker, mut manager)| {
         info!("Sharder has completed bootstrap - spawning shards.");
@@ -124,7 +122,8 @@ pub fn parse_args(results: &ArgMatches) -> Result<()> {
         parse_argv(results)?
     };
 
-    let future = start_sharder(cfg);
+    let strategy = parse_strategy(&cfg)?;
+    let future = start_sharder(cfg, strategy);
     tokio::run(future.map_err(|err| {
         error!("An error occured while sharding. {:?}", err);
     }));
@@ -158,6 +157,8 @@ fn parse_argv(results: &ArgMatches) -> Result<SpawnerOptions> {
                 Err(_) => None
             }
         }, |u| Some(u.unwrap()));
+    let shard_ids = results.value_of("shards").map(String::from)
+        .or(env::var("SHARD_IDS").ok());
     let token = results.value_of("token").map(String::from)
         .unwrap_or(env::var("DISCORD_TOKEN").expect("No Discord token provided in arguments or ENV."));
 
@@ -167,10 +168,42 @@ fn parse_argv(results: &ArgMatches) -> Result<SpawnerOptions> {
         amqp_subgroup,
         amqp_url,
         shard_count,
+        shard_ids,
         token
     })
 }
 
+fn parse_strategy(config: &SpawnerOptions) -> Result<ShardStrategy> {
+    let ids = match config.shard_ids {
+        Some(ref ids) => ids,
+        None => return Ok(match config.shard_count {
+            Some(r) => ShardStrategy::SpawnAmount(r),
+            None => ShardStrategy::Recommended
+        })
+    };
+    let total = config.shard_count.ok_or(Error::InvalidShardIds)?;
+
+    if let Some(idx) = ids.find("..") {
+        let start = ids[..idx].trim().parse::<usize>().map_err(|_| Error::InvalidShardIds)?;
+        let end = ids[idx + 2..].trim().parse::<usize>().map_err(|_| Error::InvalidShardIds)?;
+        if start >= end || end > total {
+            return Err(Error::InvalidShardIds);
+        };
+
+        Ok(ShardStrategy::Range { ids: start..end, total })
+    } else {
+        let ids = ids.split(',')
+            .map(|id| id.trim().parse::<usize>())
+            .collect::<StdResult<Vec<usize>, _>>()
+            .map_err(|_| Error::InvalidShardIds)?;
+        if ids.is_empty() || ids.iter().any(|id| *id >= total) {
+            return Err(Error::InvalidShardIds);
+        };
+
+        Ok(ShardStrategy::List { ids, total })
+    }
+}
+
 fn parse_config_file(path: String) -> Result<SpawnerOptions> {
     let file = fs::read_to_string(path)?;
 
@@ -182,3 +215,79 @@ fn parse_config_file(path: String) -> Result<SpawnerOptions> {
         Err(Error::InvalidFile)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use spectacles_gateway::ShardStrategy;
+
+    use crate::errors::Error;
+
+    use super::{parse_strategy, SpawnerOptions};
+
+    fn options(shard_ids: Option<&str>, shard_count: Option<usize>) -> SpawnerOptions {
+        SpawnerOptions {
+            amqp_url: String::from("amqp://127.0.0.1:5672/"),
+            amqp_subgroup: None,
+            amqp_group: String::from("gateway"),
+            shard_count,
+            shard_ids: shard_ids.map(String::from),
+            token: String::from("mock-token"),
+            config_path: None
+        }
+    }
+
+    fn is_invalid(shard_ids: &str, shard_count: Option<usize>) -> bool {
+        match parse_strategy(&options(Some(shard_ids), shard_count)) {
+            Err(Error::InvalidShardIds) => true,
+            _ => false
+        }
+    }
+
+    #[test]
+    fn parses_ranges() {
+        match parse_strategy(&options(Some("2..4"), Some(8))) {
+            Ok(ShardStrategy::Range { ids, total }) => {
+                assert_eq!(ids, 2..4);
+                assert_eq!(total, 8);
+            },
+            _ => panic!("Expected a range of shard IDs")
+        };
+    }
+
+    #[test]
+    fn parses_lists() {
+        match parse_strategy(&options(Some("0, 3,5"), Some(8))) {
+            Ok(ShardStrategy::List { ids, total }) => {
+                assert_eq!(ids, vec![0, 3, 5]);
+                assert_eq!(total, 8);
+            },
+            _ => panic!("Expected a list of shard IDs")
+        };
+    }
+
+    #[test]
+    fn spawns_without_ids() {
+        match parse_strategy(&options(None, Some(4))) {
+            Ok(ShardStrategy::SpawnAmount(4)) => {},
+            _ => panic!("Expected the shard count to be spawned")
+        };
+        match parse_strategy(&options(None, None)) {
+            Ok(ShardStrategy::Recommended) => {},
+            _ => panic!("Expected the recommended amount of shards to be spawned")
+        };
+    }
+
+    #[test]
+    fn rejects_invalid_ids() {
+        assert!(is_invalid("2..2", Some(8)));
+        assert!(is_invalid("4..2", Some(8)));
+        assert!(is_invalid("0..9", Some(8)));
+        assert!(is_invalid("a..b", Some(8)));
+        assert!(is_invalid("..", Some(8)));
+        assert!(is_invalid("1,two,3", Some(8)));
+        assert!(is_invalid("1,8", Some(8)));
+        assert!(is_invalid("", Some(8)));
+        assert!(is_invalid("0..4", None));
+        assert!(is_invalid("0,1", None));
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Weak}
+};
+
+use futures::{
+    Async,
+    Future,
+    Poll,
+    Stream,
+    task::{self, Task}
+};
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+
+use spectacles_model::gateway::GatewayEvent;
+
+use crate::manager::ShardEvent;
+
+/// The action taken when an event is received while the event buffer is full.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OverflowPolicy {
+    /// Stops reading from the shards' websockets until the event stream has room.
+    /// Heartbeat acknowledgements are not processed while reading is blocked, so long stalls may cause shards to reconnect.
+    Block,
+    /// Drops the oldest buffered event, to make room for the new one.
+    DropOldest,
+    /// Drops events of the provided types, starting with the incoming event and then the oldest buffered one.
+    /// If no event of these types can be dropped, reading is blocked until the event stream has room.
+    DropByEvent(Vec<GatewayEvent>)
+}
+
+/// Options for the buffer between the shards and the event stream.
+#[derive(Debug, Clone)]
+pub struct EventBufferOptions {
+    /// The maximum amount of events which may be buffered, or `None` for an unbounded buffer.
+    pub capacity: Option<usize>,
+    /// The action taken when an event is received while the buffer is full.
+    pub policy: OverflowPolicy,
+}
+
+impl Default for EventBufferOptions {
+    fn default() -> Self {
+        Self {
+            capacity: None,
+            policy: OverflowPolicy::Block
+        }
+    }
+}
+
+/// A snapshot of the metrics for the buffer between the shards and the event stream.
+#[derive(Debug, Clone, Default)]
+pub struct EventBufferMetrics {
+    /// The amount of events which are currently buffered.
+    pub buffered: usize,
+    /// The amount of events which had to wait for room in the buffer.
+    pub blocked: u64,
+    /// The amount of events dropped by the `DropOldest` policy.
+    pub dropped_oldest: u64,
+    /// The amount of events dropped by the `DropByEvent` policy, keyed by event type.
+    pub dropped_by_event: HashMap<GatewayEvent, u64>,
+}
+
+struct Shared {
+    queue: VecDeque<ShardEvent>,
+    options: EventBufferOptions,
+    metrics: EventBufferMetrics,
+    receiver: Option<Task>,
+    sender: Option<Task>,
+    closed: bool,
+    receiver_dropped: bool,
+}
+
+impl Shared {
+    /// Attempts to buffer an event, returning it if the buffer is full and the event must wait.
+    fn try_push(&mut self, event: ShardEvent) -> Result<(), ShardEvent> {
+        if self.receiver_dropped {
+            return Ok(());
+        };
+        let full = match self.options.capacity {
+            Some(capacity) => self.queue.len() >= capacity,
+            None => false
+        };
+
+        if full {
+            match self.options.policy {
+                OverflowPolicy::Block => return Err(event),
+                OverflowPolicy::DropOldest => {
+                    self.queue.pop_front();
+                    self.metrics.dropped_oldest += 1;
+                },
+                OverflowPolicy::DropByEvent(ref types) => {
+                    let droppable = |e: &ShardEvent| e.packet.t.as_ref().filter(|t| types.contains(t)).cloned();
+                    if let Some(t) = droppable(&event) {
+                        *self.metrics.dropped_by_event.entry(t).or_insert(0) += 1;
+                        return Ok(());
+                    };
+                    let oldest = self.queue.iter()
+                        .enumerate()
+                        .filter_map(|(i, e)| droppable(e).map(|t| (i, t)))
+                        .next();
+                    match oldest {
+                        Some((index, t)) => {
+                            self.queue.remove(index);
+                            *self.metrics.dropped_by_event.entry(t).or_insert(0) += 1;
+                        },
+                        None => return Err(event)
+                    };
+                }
+            };
+        };
+
+        self.queue.push_back(event);
+        if let Some(task) = self.receiver.take() {
+            task.notify();
+        };
+
+        Ok(())
+    }
+}
+
+/// Creates a new event buffer, with the provided options.
+pub fn channel(options: EventBufferOptions) -> (EventSender, EventReceiver) {
+    let shared = Arc::new(Mutex::new(Shared {
+        queue: VecDeque::new(),
+        options,
+        metrics: EventBufferMetrics::default(),
+        receiver: None,
+        sender: None,
+        closed: false,
+        receiver_dropped: false,
+    }));
+
+    (EventSender { shared: Arc::clone(&shared) }, EventReceiver { shared })
+}
+
+/// The sending half of an event buffer.
+pub struct EventSender {
+    shared: Arc<Mutex<Shared>>
+}
+
+impl EventSender {
+    /// Pushes an event into the buffer.
+    /// The returned future resolves once the event has been buffered or dropped, according to the overflow policy.
+    pub fn push(&self, event: ShardEvent) -> Push {
+        Push {
+            shared: Arc::clone(&self.shared),
+            event: Some(event),
+            blocked: false
+        }
+    }
+
+    /// Creates a handle for obtaining the metrics of this buffer, which does not keep the buffer open.
+    pub fn metrics_handle(&self) -> MetricsHandle {
+        MetricsHandle(Arc::downgrade(&self.shared))
+    }
+}
+
+impl Drop for EventSender {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock();
+        shared.closed = true;
+        if let Some(task) = shared.receiver.take() {
+            task.notify();
+        };
+    }
+}
+
+/// A future which resolves once an event has been pushed into the buffer.
+pub struct Push {
+    shared: Arc<Mutex<Shared>>,
+    event: Option<ShardEvent>,
+    blocked: bool
+}
+
+impl Future for Push {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let event = match self.event.take() {
+            Some(event) => event,
+            None => return Ok(Async::Ready(()))
+        };
+        let mut shared = self.shared.lock();
+
+        match shared.try_push(event) {
+            Ok(()) => Ok(Async::Ready(())),
+            Err(event) => {
+                if !self.blocked {
+                    self.blocked = true;
+                    shared.metrics.blocked += 1;
+                };
+                shared.sender = Some(task::current());
+                self.event = Some(event);
+
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+/// The receiving half of an event buffer.
+pub struct EventReceiver {
+    shared: Arc<Mutex<Shared>>
+}
+
+impl Stream for EventReceiver {
+    type Item = ShardEvent;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let mut shared = self.shared.lock();
+        if let Some(event) = shared.queue.pop_front() {
+            if let Some(task) = shared.sender.take() {
+                task.notify();
+            };
+            return Ok(Async::Ready(Some(event)));
+        };
+        if shared.closed {
+            return Ok(Async::Ready(None));
+        };
+        shared.receiver = Some(task::current());
+
+        Ok(Async::NotReady)
+    }
+}
+
+impl Drop for EventReceiver {
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock();
+        shared.receiver_dropped = true;
+        shared.queue.clear();
+        if let Some(task) = shared.sender.take() {
+            task.notify();
+        };
+    }
+}
+
+/// A weak handle to an event buffer, used for obtaining its metrics.
+#[derive(Clone)]
+pub struct MetricsHandle(Weak<Mutex<Shared>>);
+
+impl MetricsHandle {
+    /// Obtains a snapshot of the buffer's metrics, if the buffer is still open.
+    pub fn snapshot(&self) -> Option<EventBufferMetrics> {
+        self.0.upgrade().map(|shared| {
+            let shared = shared.lock();
+            EventBufferMetrics {
+                buffered: shared.queue.len(),
+                ..shared.metrics.clone()
+            }
+        })
+    }
+}
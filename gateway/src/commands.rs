@@ -0,0 +1,265 @@
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant}
+};
+
+use futures::sync::oneshot;
+use tokio_tungstenite::tungstenite::Message as WebsocketMessage;
+
+use crate::constants::{COMMAND_LIMIT, COMMAND_WINDOW, HEARTBEAT_RESERVE};
+
+/// A source of the current time.
+/// The command limiter may be given a mocked clock, so that its behavior can be tested without waiting.
+pub trait Clock: Send + Sync {
+    /// Returns the current instant.
+    fn now(&self) -> Instant;
+}
+
+/// A clock which reads the system's monotonic clock.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Limits the rate at which commands are sent over a shard's connection, in order to stay within Discord's limit of 120 commands every 60 seconds.
+///
+/// A portion of the limit is reserved for heartbeats, which are always sent immediately.
+/// Other commands, such as presence changes, are queued once the remaining limit has been used.
+pub struct CommandLimiter {
+    clock: Arc<Clock>,
+    /// The times at which commands were sent within the current window, oldest first.
+    sent: VecDeque<Instant>,
+    /// Commands which are waiting to be sent, along with the senders to notify once they are.
+    pending: VecDeque<(WebsocketMessage, Option<oneshot::Sender<()>>)>,
+    /// Whether or not a task is currently sending the pending commands.
+    draining: bool,
+}
+
+impl Default for CommandLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandLimiter {
+    /// Creates a new command limiter, which uses the system clock.
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    /// Creates a new command limiter, which uses the provided clock.
+    pub fn with_clock(clock: Arc<Clock>) -> Self {
+        Self {
+            clock,
+            sent: VecDeque::new(),
+            pending: VecDeque::new(),
+            draining: false
+        }
+    }
+
+    /// Records a command which was sent without waiting for the limiter, such as a heartbeat.
+    pub fn record(&mut self) {
+        let now = self.clock.now();
+        self.expire(now);
+        self.sent.push_back(now);
+    }
+
+    /// Attempts to reserve a slot for a command.
+    /// If no slot is available, the amount of time until one becomes available is returned.
+    pub fn acquire(&mut self) -> Result<(), Duration> {
+        let now = self.clock.now();
+        self.expire(now);
+        let allowed = COMMAND_LIMIT - HEARTBEAT_RESERVE;
+
+        if self.sent.len() < allowed {
+            self.sent.push_back(now);
+            Ok(())
+        } else {
+            let frees_at = self.sent[self.sent.len() - allowed] + COMMAND_WINDOW;
+            Err(frees_at - now)
+        }
+    }
+
+    /// Pushes a command into the limiter.
+    /// The command is returned if it may be sent immediately, otherwise it is queued behind any pending commands.
+    pub fn push(&mut self, message: WebsocketMessage) -> Option<WebsocketMessage> {
+        self.push_notify(message, None)
+    }
+
+    /// Pushes a command into the limiter, like `push()`, notifying the provided sender once the command may be sent.
+    /// The sender is dropped without notifying if the command is discarded.
+    pub fn push_notify(&mut self, message: WebsocketMessage, sent: Option<oneshot::Sender<()>>) -> Option<WebsocketMessage> {
+        if self.pending.is_empty() && self.acquire().is_ok() {
+            if let Some(sent) = sent {
+                let _ = sent.send(());
+            };
+            return Some(message);
+        };
+        self.pending.push_back((message, sent));
+
+        None
+    }
+
+    /// Obtains the next pending command, if a slot is available for it.
+    /// Returns `None` once there are no more pending commands.
+    pub fn next_pending(&mut self) -> Option<Result<WebsocketMessage, Duration>> {
+        if self.pending.is_empty() {
+            self.draining = false;
+            return None;
+        };
+
+        match self.acquire() {
+            Ok(()) => {
+                let (message, sent) = self.pending.pop_front().unwrap();
+                if let Some(sent) = sent {
+                    let _ = sent.send(());
+                };
+                Some(Ok(message))
+            },
+            Err(wait) => Some(Err(wait))
+        }
+    }
+
+    /// Marks the limiter as being drained, returning true if no task was draining it already.
+    pub fn start_draining(&mut self) -> bool {
+        !self.pending.is_empty() && !std::mem::replace(&mut self.draining, true)
+    }
+
+    /// The amount of commands which are waiting to be sent.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Resets the limiter for a new connection, discarding any pending commands.
+    pub fn reset(&mut self) {
+        if !self.pending.is_empty() {
+            debug!("Discarding {} pending commands for a new connection.", self.pending.len());
+        };
+        self.sent.clear();
+        self.pending.clear();
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(sent) = self.sent.front().cloned() {
+            if sent + COMMAND_WINDOW > now {
+                break;
+            };
+            self.sent.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant}
+    };
+
+    use futures::{future, Async, Future, sync::oneshot};
+    use parking_lot::Mutex;
+    use tokio_tungstenite::tungstenite::Message as WebsocketMessage;
+
+    use crate::constants::{COMMAND_LIMIT, COMMAND_WINDOW, HEARTBEAT_RESERVE};
+
+    use super::{Clock, CommandLimiter};
+
+    /// A clock which only moves when it is advanced.
+    struct MockClock(Mutex<Instant>);
+
+    impl MockClock {
+        fn advance(&self, duration: Duration) {
+            *self.0.lock() += duration;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            *self.0.lock()
+        }
+    }
+
+    fn limiter() -> (Arc<MockClock>, CommandLimiter) {
+        let clock = Arc::new(MockClock(Mutex::new(Instant::now())));
+        let limiter = CommandLimiter::with_clock(clock.clone());
+
+        (clock, limiter)
+    }
+
+    /// Pushes the provided amount of commands, returning how many of them may be sent immediately.
+    fn push(limiter: &mut CommandLimiter, amount: usize) -> usize {
+        (0..amount)
+            .filter_map(|i| limiter.push(WebsocketMessage::text(i.to_string())))
+            .count()
+    }
+
+    #[test]
+    fn queues_commands_over_the_limit() {
+        let (_, mut limiter) = limiter();
+        assert_eq!(push(&mut limiter, 120), 117);
+        assert_eq!(limiter.pending(), 3);
+        assert!(limiter.start_draining());
+        assert!(!limiter.start_draining());
+    }
+
+    #[test]
+    fn reserves_slots_for_heartbeats() {
+        let (_, mut limiter) = limiter();
+        push(&mut limiter, COMMAND_LIMIT - HEARTBEAT_RESERVE);
+        assert!(limiter.acquire().is_err());
+
+        for _ in 0..HEARTBEAT_RESERVE {
+            limiter.record();
+        }
+        assert_eq!(limiter.sent.len(), COMMAND_LIMIT);
+    }
+
+    #[test]
+    fn drains_queue_after_window() {
+        let (clock, mut limiter) = limiter();
+        push(&mut limiter, 122);
+        clock.advance(Duration::from_secs(30));
+        match limiter.next_pending() {
+            Some(Err(wait)) => assert_eq!(wait, COMMAND_WINDOW - Duration::from_secs(30)),
+            _ => panic!("A queued command was released before the window ended")
+        };
+
+        clock.advance(Duration::from_secs(30));
+        let sent: Vec<_> = (0..5).filter_map(|_| match limiter.next_pending() {
+            Some(Ok(message)) => Some(message),
+            _ => None
+        }).collect();
+        assert_eq!(sent, vec![
+            WebsocketMessage::text("117"),
+            WebsocketMessage::text("118"),
+            WebsocketMessage::text("119"),
+            WebsocketMessage::text("120"),
+            WebsocketMessage::text("121")
+        ]);
+        assert!(limiter.next_pending().is_none());
+        assert!(!limiter.start_draining());
+    }
+
+    #[test]
+    fn notifies_once_queued_command_is_sent() {
+        let (clock, mut limiter) = limiter();
+        push(&mut limiter, COMMAND_LIMIT - HEARTBEAT_RESERVE);
+
+        let (sent_tx, mut sent_rx) = oneshot::channel();
+        assert!(limiter.push_notify(WebsocketMessage::text("request"), Some(sent_tx)).is_none());
+        future::lazy(|| {
+            assert_eq!(sent_rx.poll(), Ok(Async::NotReady));
+
+            clock.advance(COMMAND_WINDOW);
+            assert_eq!(limiter.next_pending(), Some(Ok(WebsocketMessage::text("request"))));
+            assert_eq!(sent_rx.poll(), Ok(Async::Ready(())));
+
+            Ok::<_, ()>(())
+        }).wait().unwrap();
+    }
+}
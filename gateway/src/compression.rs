@@ -0,0 +1,102 @@
+use flate2::{Decompress, FlushDecompress};
+
+use crate::errors::Result;
+
+/// The suffix which Discord appends to the end of every complete zlib-stream message.
+const ZLIB_SUFFIX: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// A persistent inflate context, used for decompressing a shard's `zlib-stream` transport.
+///
+/// The same context must be used for the lifetime of a websocket connection, as every message depends on the ones before it.
+pub struct ZlibStream {
+    /// Binary frames that have been received, but do not yet form a complete message.
+    buffer: Vec<u8>,
+    /// The underlying zlib decompressor.
+    inflater: Decompress,
+}
+
+impl ZlibStream {
+    /// Creates a new inflate context, for a fresh websocket connection.
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            inflater: Decompress::new(true),
+        }
+    }
+
+    /// Pushes a binary frame into the stream.
+    /// If the frame completes a message, the decompressed bytes of that message are returned.
+    pub fn push(&mut self, frame: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.buffer.extend_from_slice(frame);
+        if !self.buffer.ends_with(&ZLIB_SUFFIX) {
+            return Ok(None);
+        };
+
+        let mut output = Vec::with_capacity(self.buffer.len() * 4);
+        let mut offset = 0;
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(self.buffer.len() * 2);
+            };
+            let total_in = self.inflater.total_in();
+            let total_out = self.inflater.total_out();
+            self.inflater.decompress_vec(&self.buffer[offset..], &mut output, FlushDecompress::Sync)?;
+            offset += (self.inflater.total_in() - total_in) as usize;
+
+            let finished = offset >= self.buffer.len() && output.len() < output.capacity();
+            let stalled = self.inflater.total_in() == total_in && self.inflater.total_out() == total_out;
+            if finished || stalled {
+                break;
+            };
+        }
+        self.buffer.clear();
+
+        Ok(Some(output))
+    }
+}
+
+impl Default for ZlibStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ZlibStream;
+
+    const HELLO: &[u8] = br#"{"op":10,"d":{"heartbeat_interval":41250,"_trace":["gateway-prd-main-w7j2"]},"s":null,"t":null}"#;
+    const HEARTBEAT_ACK: &[u8] = br#"{"op":11,"d":null,"s":null,"t":null}"#;
+
+    /// The first message of a recorded zlib-stream connection, which includes the zlib header.
+    const HELLO_FRAME: [u8; 93] = [
+        0x78, 0x9c, 0x24, 0xc9, 0xdd, 0x0a, 0x40, 0x40, 0x10, 0x06, 0xd0, 0x77, 0xf9, 0xae, 0x77, 0x0b,
+        0x91, 0x9a, 0x57, 0x91, 0x34, 0xec, 0xe4, 0xa7, 0xb5, 0xb4, 0x06, 0x49, 0xde, 0x9d, 0x72, 0x77,
+        0xea, 0xdc, 0x58, 0x56, 0x50, 0x9a, 0x18, 0x38, 0xd0, 0x8d, 0x41, 0x38, 0x6a, 0x2b, 0xac, 0xcd,
+        0x18, 0x54, 0xe2, 0xc1, 0x1e, 0x94, 0xa7, 0x59, 0xf1, 0x7d, 0xa3, 0x91, 0x3b, 0x01, 0x55, 0xe8,
+        0x59, 0xe5, 0xe4, 0xcb, 0xae, 0xd1, 0xd9, 0x99, 0xc7, 0x60, 0xcf, 0x72, 0xca, 0x50, 0x3f, 0x06,
+        0x1b, 0x28, 0xec, 0xde, 0x1b, 0xe8, 0x8f, 0xe7, 0x05, 0x00, 0x00, 0xff, 0xff,
+    ];
+    /// The second message of the same connection, which refers back to the first.
+    const HEARTBEAT_ACK_FRAME: [u8; 16] = [
+        0xaa, 0x86, 0x18, 0x6f, 0x08, 0x36, 0x1e, 0x22, 0x85, 0xa1, 0x06, 0x00, 0x00, 0x00, 0xff, 0xff,
+    ];
+
+    #[test]
+    fn message_split_across_frames() {
+        let mut zlib = ZlibStream::default();
+        assert_eq!(zlib.push(&HELLO_FRAME[..30]).unwrap(), None);
+        assert_eq!(zlib.push(&HELLO_FRAME[30..60]).unwrap(), None);
+        assert_eq!(zlib.push(&HELLO_FRAME[60..]).unwrap().unwrap(), HELLO);
+    }
+
+    #[test]
+    fn context_reused_across_messages() {
+        let mut zlib = ZlibStream::new();
+        assert_eq!(zlib.push(&HELLO_FRAME).unwrap().unwrap(), HELLO);
+        assert_eq!(zlib.push(&HEARTBEAT_ACK_FRAME).unwrap().unwrap(), HEARTBEAT_ACK);
+
+        // Without the first message, the second cannot be decompressed.
+        assert!(ZlibStream::new().push(&HEARTBEAT_ACK_FRAME).is_err());
+    }
+}
@@ -1,2 +1,22 @@
+use std::time::Duration;
+
 pub const GATEWAY_VERSION: u8 = 6;
-pub const API_BASE: &'static str = "https://discordapp.com/api/v7";
\ No newline at end of file
+pub const API_BASE: &'static str = "https://discordapp.com/api/v7";
+/// The amount of time that must pass between identifies in the same concurrency bucket.
+pub const IDENTIFY_DELAY: Duration = Duration::from_secs(5);
+/// The amount of time after which the session start limit resets, once it has been reset.
+pub const SESSION_START_RESET: Duration = Duration::from_secs(60 * 60 * 24);
+/// The close code used when shutting down or restarting shards, which keeps their sessions resumable.
+pub const RESUME_CLOSE_CODE: u16 = 4000;
+/// How long to wait for the gateway to acknowledge a close frame, before the connection is dropped.
+pub const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The capacity of the channel between the shards' websockets and the manager, when the event buffer is unbounded.
+pub const MESSAGE_BUFFER: usize = 1024;
+/// The amount of commands which may be sent over a single gateway connection within `COMMAND_WINDOW`.
+pub const COMMAND_LIMIT: usize = 120;
+/// The window in which `COMMAND_LIMIT` applies.
+pub const COMMAND_WINDOW: Duration = Duration::from_secs(60);
+/// The amount of commands within each window which are reserved for heartbeats.
+pub const HEARTBEAT_RESERVE: usize = 3;
+/// How long to wait for the next guild members chunk of a request, before the request times out.
+pub const MEMBER_CHUNK_TIMEOUT: Duration = Duration::from_secs(10);
@@ -5,9 +5,12 @@ use std::{
     result::Result as StdResult,
 };
 
+use flate2::DecompressError;
 use futures::sync::mpsc::SendError;
+use redis::RedisError;
 use reqwest::Error as ReqwestError;
 use serde_json::Error as JsonError;
+use spectacles_model::{etf::Error as EtfError, gateway::CloseCodes};
 use tokio::timer::Error as TimerError;
 use tokio_tungstenite::tungstenite::{
     Error as TungsteniteError,
@@ -22,10 +25,18 @@ pub type Result<T> = StdResult<T, Error>;
 pub enum Error {
     Tungstenite(TungsteniteError),
     Json(JsonError),
+    Etf(EtfError),
     Reqwest(ReqwestError),
+    Redis(RedisError),
     Timer(TimerError),
     InvalidTokenError,
+    Closed(CloseCodes),
+    ShardNotFound(usize),
+    InvalidGatewayUrl(String),
+    InvalidShardIds,
+    MemberChunkTimeout,
     Io(IoError),
+    Decompress(DecompressError),
     TungsteniteSend(SendError<TungsteniteMessage>)
 }
 
@@ -39,11 +50,24 @@ impl StdError for Error {
     fn description(&self) -> &str {
         match self {
             Error::Reqwest(e) => e.description(),
+            Error::Redis(e) => e.description(),
             Error::Timer(e) => e.description(),
             Error::Tungstenite(e) => e.description(),
             Error::Io(e) => e.description(),
+            Error::Decompress(e) => e.description(),
             Error::TungsteniteSend(e) => e.description(),
             Error::Json(e) => e.description(),
+            Error::Etf(e) => e.description(),
+            Error::Closed(_) =>
+                "The gateway closed the connection with a close code that cannot be recovered from.",
+            Error::ShardNotFound(_) =>
+                "The requested shard has not been spawned by this manager.",
+            Error::InvalidGatewayUrl(_) =>
+                "The gateway URL could not be parsed, or does not contain a host to connect to.",
+            Error::InvalidShardIds =>
+                "The shard IDs to spawn must be lower than the total amount of shards, and must not contain duplicates.",
+            Error::MemberChunkTimeout =>
+                "The gateway stopped sending guild members chunks before the request was complete.",
             Error::InvalidTokenError =>
                 "The token provided was not accepted by Discord. Please check that your token is correct and try again."
         }
@@ -62,6 +86,12 @@ impl From<IoError> for Error {
     }
 }
 
+impl From<DecompressError> for Error {
+    fn from(err: DecompressError) -> Self {
+        Error::Decompress(err)
+    }
+}
+
 impl From<TimerError> for Error {
     fn from(err: TimerError) -> Self {
         Error::Timer(err)
@@ -80,6 +110,12 @@ impl From<ReqwestError> for Error {
     }
 }
 
+impl From<RedisError> for Error {
+    fn from(err: RedisError) -> Self {
+        Error::Redis(err)
+    }
+}
+
 impl From<SendError<TungsteniteMessage>> for Error {
     fn from(err: SendError<TungsteniteMessage>) -> Self {
         Error::TungsteniteSend(err)
@@ -91,3 +127,9 @@ impl From<JsonError> for Error {
         Error::Json(err)
     }
 }
+
+impl From<EtfError> for Error {
+    fn from(err: EtfError) -> Self {
+        Error::Etf(err)
+    }
+}
\ No newline at end of file
@@ -45,12 +45,28 @@
 
 #[warn(rust_2018_idioms)]
 
+pub use buffer::{EventBufferMetrics, EventBufferOptions, OverflowPolicy};
+pub use commands::{Clock, CommandLimiter, SystemClock};
 pub use errors::{Error, Result};
 pub use manager::*;
-pub use shard::Shard;
+pub use limiter::IdentifyLimiter;
+pub use members::GuildMembersStream;
+pub use metrics::ShardMetrics;
+pub use queue::{ReconnectQueue, RedisQueue};
+pub use session::{FileSessionStore, RedisSessionStore, SessionInfo, SessionStore};
+pub use shard::{Encoding, Heartbeat, LifecycleEvent, Shard, ShardLifecycle, ShardOptions, ShardState, ShardStream};
 
 mod manager;
 mod shard;
 mod constants;
 mod errors;
 mod queue;
+mod compression;
+mod limiter;
+mod buffer;
+mod commands;
+mod members;
+mod session;
+mod metrics;
+#[cfg(feature = "mock")]
+pub mod mock;
@@ -0,0 +1,89 @@
+use std::{
+    cmp,
+    sync::Arc,
+    time::{Duration, Instant}
+};
+
+use futures::Future;
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use tokio::timer::Delay;
+
+use spectacles_model::gateway::SessionStartLimit;
+
+use crate::{
+    constants::{IDENTIFY_DELAY, SESSION_START_RESET},
+    errors::Error,
+    queue::ReconnectQueue
+};
+
+/// An in-memory identify queue, which schedules identifies according to the bot's session start limit and maximum identify concurrency.
+///
+/// Shards are placed into concurrency buckets by `shard_id % max_concurrency`.
+/// Each bucket may identify once every five seconds, and buckets identify in parallel.
+#[derive(Clone, Debug)]
+pub struct IdentifyLimiter {
+    state: Arc<Mutex<LimiterState>>
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    total: i32,
+    remaining: i32,
+    reset_at: Instant,
+    max_concurrency: usize,
+    buckets: HashMap<usize, Instant>,
+}
+
+impl IdentifyLimiter {
+    /// Creates a new limiter from the session start limit returned by Discord.
+    pub fn new(limit: &SessionStartLimit) -> Self {
+        let reset_after = Duration::from_millis(cmp::max(limit.reset_after, 0) as u64);
+
+        Self {
+            state: Arc::new(Mutex::new(LimiterState {
+                total: limit.total,
+                remaining: limit.remaining,
+                reset_at: Instant::now() + reset_after,
+                max_concurrency: cmp::max(limit.max_concurrency, 1),
+                buckets: HashMap::new(),
+            }))
+        }
+    }
+
+    /// Reserves an identify slot for the provided shard.
+    /// The returned future resolves once the shard is allowed to identify.
+    pub fn acquire(&self, shard_id: usize) -> impl Future<Item = (), Error = Error> + Send {
+        let now = Instant::now();
+        let mut state = self.state.lock();
+
+        if now >= state.reset_at {
+            state.remaining = state.total;
+            state.reset_at = now + SESSION_START_RESET;
+        };
+        let mut ready = now;
+        if state.remaining <= 0 {
+            warn!("[Shard {}] Session start limit exhausted, waiting {:?} for it to reset.", shard_id, state.reset_at - now);
+            ready = state.reset_at;
+            state.remaining = state.total;
+            state.reset_at = ready + SESSION_START_RESET;
+        };
+        state.remaining -= 1;
+
+        let bucket = shard_id % state.max_concurrency;
+        let slot = match state.buckets.get(&bucket) {
+            Some(next) => cmp::max(*next, ready),
+            None => ready
+        };
+        state.buckets.insert(bucket, slot + IDENTIFY_DELAY);
+        debug!("[Shard {}] Identify scheduled in bucket {}, {} session starts remaining.", shard_id, bucket, state.remaining);
+
+        Delay::new(slot).from_err()
+    }
+}
+
+impl ReconnectQueue for IdentifyLimiter {
+    fn push_back(&self, shard_id: usize) -> Box<Future<Item = (), Error = Error> + Send> {
+        Box::new(self.acquire(shard_id))
+    }
+}
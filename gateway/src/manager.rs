@@ -1,29 +1,40 @@
 use std::{
-    sync::Arc,
-    time::Duration,
-    time::Instant
+    ops::Range,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering}
+    },
+    time::Duration
 };
 
 use futures::{
-    future::Future,
+    future::{self, Either, Future},
     Poll,
     Stream,
-    sync::mpsc::{unbounded, UnboundedReceiver}
+    sync::{
+        mpsc::{channel, Receiver, Sender, unbounded, UnboundedReceiver},
+        oneshot
+    }
 };
 use futures::future::Loop;
 use futures::sync::mpsc::UnboundedSender;
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
+use native_tls::TlsConnector;
 use parking_lot::{Mutex, RwLock};
-use tokio::timer::Delay;
+use reqwest::r#async::Client;
 use tokio_tungstenite::tungstenite::Message as TungsteniteMessage;
 
-use spectacles_model::gateway::{GatewayBot, Opcodes, ReceivePacket};
+use spectacles_model::gateway::{DispatchEvent, GatewayBot, GatewayEvent, Opcodes, ReceivePacket, SessionStartLimit};
 
 use crate::{
-    constants::API_BASE,
+    buffer::{self, EventBufferMetrics, EventBufferOptions, EventReceiver, MetricsHandle},
+    constants::{API_BASE, MESSAGE_BUFFER, RESUME_CLOSE_CODE},
     errors::*,
-    queue::{MessageSink, MessageSinkError},
-    shard::{Shard, ShardAction}
+    limiter::IdentifyLimiter,
+    metrics::{self, ShardMetrics},
+    queue::{MessageSink, MessageSinkError, ReconnectQueue, ShardMessage},
+    session::{SessionInfo, SessionStore},
+    shard::{LifecycleEvent, Shard, ShardAction, ShardLifecycle, ShardOptions}
 };
 
 /// The strategy in which you would like to spawn shards.
@@ -32,7 +43,24 @@ pub enum ShardStrategy {
     /// The spawner will automatically spawn shards based on the amount recommended by Discord.
     Recommended,
     /// Spawns shards according to the amount specified, starting from shard 0.
-    SpawnAmount(usize)
+    SpawnAmount(usize),
+    /// Spawns a range of shard IDs, identifying with the provided total amount of shards.
+    /// This is useful for splitting a bot's shards between several clusters.
+    /// The IDs must be lower than the total, otherwise building the manager fails with `Error::InvalidShardIds`.
+    Range {
+        /// The shard IDs which this manager will spawn.
+        ids: Range<usize>,
+        /// The total amount of shards across every cluster.
+        total: usize
+    },
+    /// Spawns an explicit list of shard IDs, identifying with the provided total amount of shards.
+    /// The IDs must be lower than the total and must not repeat, otherwise building the manager fails with `Error::InvalidShardIds`.
+    List {
+        /// The shard IDs which this manager will spawn.
+        ids: Vec<usize>,
+        /// The total amount of shards across every cluster.
+        total: usize
+    }
 }
 
 #[derive(Clone)]
@@ -44,11 +72,36 @@ pub struct ShardEvent {
     pub packet: ReceivePacket,
 }
 
+impl ShardEvent {
+    /// Decodes the packet of this event into its typed dispatch event.
+    pub fn decode(&self) -> DispatchEvent {
+        DispatchEvent::from_packet(&self.packet)
+    }
+}
+
+/// An error which occurred while handling a message received by a shard.
+#[derive(Debug)]
+pub struct ShardError {
+    /// The ID of the shard which received the message.
+    pub shard_id: usize,
+    /// The error that occurred.
+    pub error: Error,
+}
+
+/// A typed Discord Gateway event received for a shard.
+#[derive(Clone)]
+pub struct ShardDispatch {
+    /// The shard which emitted this event.
+    pub shard: ManagerShard,
+    /// The decoded event.
+    pub event: DispatchEvent,
+}
+
 /// A collection of shards, keyed by shard ID.
 pub type ShardMap = HashMap<usize, Arc<Mutex<Shard>>>;
 /// An alias for a shard spawned with the sharding manager.
 pub type ManagerShard = Arc<Mutex<Shard>>;
-type MessageStream = UnboundedReceiver<(ManagerShard, TungsteniteMessage)>;
+type MessageStream = Receiver<ShardMessage>;
 
 /// A stream of shards being spawned and emitting the ready event.
 pub struct Spawner {
@@ -72,13 +125,19 @@ impl Stream for Spawner {
 
 /// A stream of incoming Discord events for a shard.
 pub struct EventHandler {
-    inner: UnboundedReceiver<ShardEvent>
+    inner: EventReceiver
 }
 
 impl EventHandler {
-    fn new(receiver: UnboundedReceiver<ShardEvent>) -> Self {
+    fn new(receiver: EventReceiver) -> Self {
         EventHandler { inner: receiver }
     }
+
+    /// Converts this stream into a stream of typed dispatch events.
+    /// Events which cannot be decoded are yielded as `DispatchEvent::Raw`.
+    pub fn dispatches(self) -> DispatchStream {
+        DispatchStream { inner: self }
+    }
 }
 
 impl Stream for EventHandler {
@@ -90,168 +149,852 @@ impl Stream for EventHandler {
     }
 }
 
+/// A stream of lifecycle events for every shard spawned by the manager.
+pub struct LifecycleStream {
+    inner: UnboundedReceiver<ShardLifecycle>
+}
+
+impl LifecycleStream {
+    fn new(receiver: UnboundedReceiver<ShardLifecycle>) -> Self {
+        LifecycleStream { inner: receiver }
+    }
+}
+
+impl Stream for LifecycleStream {
+    type Item = ShardLifecycle;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.inner.poll()
+    }
+}
+
+/// A stream of typed Discord events for a shard, created with `EventHandler::dispatches()`.
+pub struct DispatchStream {
+    inner: EventHandler
+}
+
+impl Stream for DispatchStream {
+    type Item = ShardDispatch;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.inner.poll().map(|poll| poll.map(|event| event.map(|e| ShardDispatch {
+            event: e.decode(),
+            shard: e.shard
+        })))
+    }
+}
+
+/// A stream of errors which occurred while handling messages received by shards.
+pub struct ErrorStream {
+    inner: UnboundedReceiver<ShardError>
+}
+
+impl ErrorStream {
+    fn new(receiver: UnboundedReceiver<ShardError>) -> Self {
+        ErrorStream { inner: receiver }
+    }
+}
+
+impl Stream for ErrorStream {
+    type Item = ShardError;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.inner.poll()
+    }
+}
+
 #[derive(Clone)]
 struct SpawnerLoop {
     shardmap: Arc<RwLock<ShardMap>>,
-    sink_tx: UnboundedSender<(ManagerShard, TungsteniteMessage)>,
+    ctx: ShardContext,
+    ids: Vec<usize>,
     current: usize,
     total: usize,
     sender: UnboundedSender<ManagerShard>,
     token: String,
     ws: String,
+    options: ShardOptions,
+    shard_options: HashMap<usize, ShardOptions>,
+    sessions: HashMap<usize, SessionInfo>,
+    closing: Arc<AtomicBool>,
 }
 
 /// The central hub for all shards, where shards are spawned and maintained.
 pub struct ShardManager {
     /// The token used by this manager to spawn shards.
     pub token: String,
-    /// The total amount of shards that this manager will attempt to spawn.
+    /// The total amount of shards that the bot is using, which is sent when identifying.
     pub total_shards: usize,
+    /// The IDs of the shards that this manager will attempt to spawn.
+    pub shard_ids: Vec<usize>,
     /// A collection of shards that have been spawned.
     pub shards: Arc<RwLock<ShardMap>>,
-    event_sender: Option<UnboundedSender<ShardEvent>>,
+    /// The connection options that will be used for every shard spawned by this manager.
+    /// Options such as transport compression and gateway intents should be set before calling `start_spawn()`.
+    pub options: ShardOptions,
+    /// Connection options for specific shards, keyed by shard ID, which are used instead of `options`.
+    pub shard_options: HashMap<usize, ShardOptions>,
+    /// The session start limit of the bot, as reported by Discord when this manager was created.
+    pub session_start_limit: SessionStartLimit,
+    /// The queue through which every shard identify is scheduled.
+    /// By default, this is an in-memory queue which respects the session start limit.
+    /// A shared queue, such as a [`RedisQueue`], may be used to coordinate identifies between several processes.
+    ///
+    /// [`RedisQueue`]: struct.RedisQueue.html
+    pub queue: Arc<ReconnectQueue>,
+    /// The store which shard sessions are saved to on shutdown, and restored from when spawning.
+    /// Restored sessions are resumed, rather than identifying again, and are removed from the store once loaded.
+    pub session_store: Option<Arc<SessionStore>>,
+    /// The capacity and overflow policy of the buffer between the shards and the event stream.
+    /// By default, the buffer is unbounded.
+    pub event_buffer: EventBufferOptions,
+    event_metrics: Option<MetricsHandle>,
+    lifecycle_sender: Option<UnboundedSender<ShardLifecycle>>,
+    error_sender: Option<UnboundedSender<ShardError>>,
     message_stream: Option<MessageStream>,
+    context: Option<ShardContext>,
+    closing: Arc<AtomicBool>,
+    stop_events: Option<oneshot::Sender<()>>,
     ws_uri: String
 }
 
+/// A builder for a [`ShardManager`], which configures how the manager reaches the gateway.
+///
+/// [`ShardManager`]: struct.ShardManager.html
+pub struct ShardManagerBuilder {
+    token: String,
+    strategy: ShardStrategy,
+    api_base: String,
+    gateway: Option<(String, usize)>,
+    http: Option<Client>,
+    options: ShardOptions
+}
+
+impl ShardManagerBuilder {
+    /// Sets the API base URL which the gateway information is fetched from.
+    pub fn api_base(mut self, url: &str) -> Self {
+        self.api_base = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sets the gateway URL and the recommended amount of shards, which skips fetching the gateway information.
+    /// As the session start limit is then unknown, shards identify one at a time.
+    pub fn gateway(mut self, url: &str, shard_count: usize) -> Self {
+        self.gateway = Some((url.to_string(), shard_count));
+        self
+    }
+
+    /// Sets the HTTP client which is used to fetch the gateway information.
+    pub fn http_client(mut self, client: Client) -> Self {
+        self.http = Some(client);
+        self
+    }
+
+    /// Sets the connection options for every shard spawned by the manager.
+    /// This replaces any TLS connector or large threshold which was previously set.
+    pub fn options(mut self, options: ShardOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the TLS connector which shards use to connect to the gateway.
+    pub fn tls_connector(mut self, connector: TlsConnector) -> Self {
+        self.options.tls_connector = Some(connector);
+        self
+    }
+
+    /// Sets the total number of members at which the gateway stops sending offline members of a guild.
+    pub fn large_threshold(mut self, threshold: i32) -> Self {
+        self.options.large_threshold = threshold;
+        self
+    }
+
+    /// Creates the shard manager, fetching the gateway information if no gateway URL was provided.
+    pub fn build(self) -> impl Future<Item = ShardManager, Error = Error> {
+        let Self { token, strategy, api_base, gateway, http, options } = self;
+
+        let gateway: Box<Future<Item = GatewayBot, Error = Error> + Send> = match gateway {
+            Some((url, shards)) => Box::new(future::ok(GatewayBot {
+                url,
+                shards,
+                session_start_limit: SessionStartLimit {
+                    total: 1000,
+                    remaining: 1000,
+                    reset_after: 0,
+                    max_concurrency: 1
+                }
+            })),
+            None => Box::new(http.unwrap_or_else(Client::new)
+                .get(format!("{}/gateway/bot", api_base).as_str())
+                .header("Authorization", token.clone()).send()
+                .and_then(|mut resp| resp.json::<GatewayBot>())
+                .map_err(Error::from)
+            )
+        };
+
+        gateway.and_then(move |gb| ShardManager::from_gateway(token, strategy, gb, options))
+    }
+}
+
 impl ShardManager {
     /// Creates a new cluster, with the provided Discord API token.
     pub fn new(token: String, strategy: ShardStrategy) -> impl Future<Item=ShardManager, Error=Error> {
+        Self::builder(token, strategy).build()
+    }
+
+    /// Creates a new cluster, which fetches the gateway information from the provided API base URL instead of Discord's.
+    /// This is useful for connecting to a local gateway, such as a [`MockGateway`].
+    ///
+    /// [`MockGateway`]: mock/struct.MockGateway.html
+    pub fn with_api_base(token: String, strategy: ShardStrategy, api_base: &str) -> impl Future<Item=ShardManager, Error=Error> {
+        Self::builder(token, strategy).api_base(api_base).build()
+    }
+
+    /// Creates a builder for a new cluster, with the provided Discord API token.
+    pub fn builder(token: String, strategy: ShardStrategy) -> ShardManagerBuilder {
         let token = if token.starts_with("Bot ") {
             token
         } else {
             format!("Bot {}", token)
         };
 
-        use reqwest::r#async::Client;
-        Client::new().get(format!("{}/gateway/bot", API_BASE).as_str())
-            .header("Authorization", token.clone()).send()
-            .and_then(|mut resp| resp.json::<GatewayBot>())
-            .map_err(Error::from)
-            .map(move |gb| {
-                let shard_count = match strategy {
-                    ShardStrategy::Recommended => gb.shards,
-                    ShardStrategy::SpawnAmount(int) => int
-                };
+        ShardManagerBuilder {
+            token,
+            strategy,
+            api_base: API_BASE.to_string(),
+            gateway: None,
+            http: None,
+            options: ShardOptions::default()
+        }
+    }
 
-                Self {
-                    token,
-                    total_shards: shard_count,
-                    shards: Arc::new(RwLock::new(HashMap::new())),
-                    event_sender: None,
-                    message_stream: None,
-                    ws_uri: gb.url
-                }
+    fn from_gateway(token: String, strategy: ShardStrategy, gb: GatewayBot, options: ShardOptions) -> Result<Self> {
+        let (shard_ids, shard_count): (Vec<usize>, usize) = match strategy {
+            ShardStrategy::Recommended => ((0..gb.shards).collect(), gb.shards),
+            ShardStrategy::SpawnAmount(int) => ((0..int).collect(), int),
+            ShardStrategy::Range { ids, total } => (ids.collect(), total),
+            ShardStrategy::List { ids, total } => (ids, total)
+        };
+        let mut seen = HashSet::new();
+        if !shard_ids.iter().all(|id| *id < shard_count && seen.insert(*id)) {
+            return Err(Error::InvalidShardIds);
+        };
+
+        Ok(Self {
+            token,
+            total_shards: shard_count,
+            shard_ids,
+            shards: Arc::new(RwLock::new(HashMap::new())),
+            options,
+            shard_options: HashMap::new(),
+            queue: Arc::new(IdentifyLimiter::new(&gb.session_start_limit)),
+            session_start_limit: gb.session_start_limit,
+            session_store: None,
+            event_buffer: EventBufferOptions::default(),
+            event_metrics: None,
+            lifecycle_sender: None,
+            error_sender: None,
+            message_stream: None,
+            context: None,
+            closing: Arc::new(AtomicBool::new(false)),
+            stop_events: None,
+            ws_uri: gb.url
+        })
+    }
+
+    /// Obtains the heartbeat latency of each spawned shard, keyed by shard ID.
+    /// Shards which have not yet received a heartbeat acknowledgement are omitted.
+    pub fn latencies(&self) -> HashMap<usize, Duration> {
+        self.shards.read().iter()
+            .filter_map(|(id, shard)| {
+                let latency = shard.lock().heartbeat.lock().latency();
+                latency.map(|l| (*id, l))
             })
+            .collect()
+    }
+
+    /// Obtains a snapshot of the health metrics of each spawned shard, keyed by shard ID.
+    pub fn metrics(&self) -> HashMap<usize, ShardMetrics> {
+        self.shards.read().iter()
+            .map(|(id, shard)| (*id, shard.lock().metrics()))
+            .collect()
+    }
+
+    /// Encodes the health metrics of each spawned shard in the Prometheus text exposition format.
+    pub fn prometheus_metrics(&self) -> String {
+        metrics::encode_prometheus(&self.metrics())
+    }
+
+    /// Creates a stream of lifecycle events for the shards spawned by this manager.
+    /// This must be called before `start_spawn()`, otherwise no events will be emitted.
+    pub fn lifecycle(&mut self) -> LifecycleStream {
+        let (sender, receiver) = unbounded();
+        self.lifecycle_sender = Some(sender);
+
+        LifecycleStream::new(receiver)
+    }
+
+    /// Creates a stream of errors which occur while handling the messages received by shards, such as packets which fail to parse.
+    /// These errors are always logged, and the shard continues to receive messages.
+    /// This must be called before `start_spawn()`, otherwise no errors will be emitted.
+    pub fn errors(&mut self) -> ErrorStream {
+        let (sender, receiver) = unbounded();
+        self.error_sender = Some(sender);
+
+        ErrorStream::new(receiver)
+    }
+
+    /// Obtains the metrics of the buffer between the shards and the event stream.
+    pub fn event_metrics(&self) -> EventBufferMetrics {
+        self.event_metrics.as_ref()
+            .and_then(|handle| handle.snapshot())
+            .unwrap_or_default()
+    }
+
+    /// Gracefully shuts down every shard spawned by this manager, and stops spawning any remaining shards.
+    /// Shards are disconnected with a resumable close code, so that their sessions may be resumed later.
+    /// The returned future resolves once every connection has wound down, after which the event stream ends.
+    pub fn shutdown(&mut self) -> impl Future<Item = (), Error = Error> + Send {
+        info!("Shutting down {} shards.", self.shards.read().len());
+        self.closing.store(true, Ordering::SeqCst);
+        let stop_events = self.stop_events.take();
+        let disconnects: Vec<_> = self.shards.read().values()
+            .map(|shard| shard.lock().disconnect(RESUME_CLOSE_CODE))
+            .collect();
+
+        let shards = Arc::clone(&self.shards);
+        let store = self.session_store.clone();
+
+        future::join_all(disconnects).and_then(move |_| -> Box<Future<Item = (), Error = Error> + Send> {
+            match store {
+                Some(store) => {
+                    let sessions: Vec<SessionInfo> = shards.read().values()
+                        .filter_map(|shard| shard.lock().session())
+                        .collect();
+                    info!("Saving {} shard sessions.", sessions.len());
+                    store.save(sessions)
+                },
+                None => Box::new(future::ok(()))
+            }
+        }).then(move |result| {
+            if let Some(stop) = stop_events {
+                let _ = stop.send(());
+            };
+            info!("All shards have been shut down.");
+            result
+        })
+    }
+
+    /// Restarts the shard with the provided ID, by closing its connection and resuming its session.
+    /// If the shard has no session to resume, it will identify with the gateway again.
+    pub fn restart_shard(&self, id: usize) -> Box<Future<Item = (), Error = Error> + Send> {
+        let shard = self.shards.read().get(&id).cloned();
+        let (shard, ctx) = match (shard, self.context.clone()) {
+            (Some(shard), Some(ctx)) => (shard, ctx),
+            _ => return Box::new(future::err(Error::ShardNotFound(id)))
+        };
+        let disconnect = shard.lock().disconnect(RESUME_CLOSE_CODE);
+
+        Box::new(disconnect.and_then(move |_| {
+            let reconnect = shard.lock().autoreconnect();
+            reconnect.map(move |_| {
+                ctx.forward_messages(&shard);
+                info!("[Shard {}] Shard has been restarted.", id);
+            })
+        }))
     }
 
     /// Spawns shards up to the specified amount and identifies them with Discord.
     pub fn start_spawn(&mut self) -> (Spawner, EventHandler) {
-        let (sender, receiver) = unbounded();
+        // Websocket reads wait for room in this channel, so that a full event buffer may block them.
+        let (sender, receiver) = channel(self.event_buffer.capacity.unwrap_or(MESSAGE_BUFFER));
         self.message_stream = Some(receiver);
         let (tx, rx) = unbounded();
-        debug!("Attempting to spawn {} shards.", &self.total_shards);
+        debug!("Attempting to spawn {} of {} shards.", self.shard_ids.len(), &self.total_shards);
+        let ctx = ShardContext {
+            sink_tx: sender,
+            queue: Arc::clone(&self.queue),
+            lifecycle: self.lifecycle_sender.clone(),
+            errors: self.error_sender.clone(),
+        };
+        self.context = Some(ctx.clone());
         let initial = SpawnerLoop {
             current: 0,
             shardmap: Arc::clone(&self.shards),
-            sink_tx: sender.clone(),
+            ctx: ctx.clone(),
+            ids: self.shard_ids.clone(),
             sender: tx,
             total: self.total_shards,
             token: self.token.clone(),
             ws: self.ws_uri.clone(),
+            options: self.options.clone(),
+            shard_options: self.shard_options.clone(),
+            sessions: HashMap::new(),
+            closing: Arc::clone(&self.closing),
+        };
+
+        if initial.ids.is_empty() {
+            return (Spawner::new(rx), self.start_event_stream(ctx));
         };
 
-        tokio::spawn(futures::future::loop_fn(initial, move |state| {
-            Delay::new(Instant::now() + Duration::from_secs(6)).from_err()
-                .map(|_| state)
-                .and_then(move |mut state| {
-                    Shard::new(state.token.clone(), [state.current, state.total], state.ws.clone())
-                        .map(move |shard| {
-                            let wrapped = ManagerShard::new(Mutex::new(shard));
-                            state.shardmap.write().insert(wrapped.lock().info[0], Arc::clone(&wrapped));
-                            let sink = MessageSink {
-                                shard: Arc::clone(&wrapped),
-                                sender: state.sink_tx.clone(),
-                            };
-                            let split = wrapped.lock().stream.lock().take().unwrap().map_err(MessageSinkError::from);
-                            tokio::spawn(split.forward(sink)
-                                .map(|_| ())
-                                .map_err(|e| error!("Failed to forward shard messages to the sink. {:?}", e))
-                            );
-                            state.sender.unbounded_send(wrapped).expect("Failed to send shard to stream");
-                            state.current += 1;
-
-                            state
-                        })
-                })
-                .map(|state| {
-                    if state.current == state.total {
+        // Saved sessions are removed once loaded, so that a session which can no longer be resumed is only attempted once.
+        // Sessions are saved again when the manager shuts down.
+        let load: Box<Future<Item = HashMap<usize, SessionInfo>, Error = Error> + Send> = match self.session_store {
+            Some(ref store) => {
+                let store = Arc::clone(store);
+                let ids = initial.ids.clone();
+                Box::new(store.load().and_then(move |sessions| {
+                    let loaded: Vec<usize> = ids.into_iter().filter(|id| sessions.contains_key(id)).collect();
+                    store.remove(loaded).then(move |result| -> Result<HashMap<usize, SessionInfo>> {
+                        if let Err(err) = result {
+                            warn!("Failed to remove the loaded sessions from the session store. {:?}", err);
+                        };
+                        Ok(sessions)
+                    })
+                }))
+            },
+            None => Box::new(future::ok(HashMap::new()))
+        };
+        let sessions = load.then(|result| -> Result<HashMap<usize, SessionInfo>> {
+            Ok(result.unwrap_or_else(|err| {
+                warn!("Failed to load saved sessions, every shard will identify. {:?}", err);
+                HashMap::new()
+            }))
+        });
+
+        // Shards which will identify wait for an identify slot before connecting, so that the slot is used as soon as the gateway says hello.
+        tokio::spawn(sessions.and_then(move |sessions| futures::future::loop_fn(SpawnerLoop { sessions, ..initial }, move |mut state| {
+            if state.closing.load(Ordering::SeqCst) {
+                debug!("Manager is shutting down, no more shards will be spawned.");
+                return Either::A(future::ok(Loop::Break(())));
+            };
+            let id = state.ids[state.current];
+            let options = state.shard_options.get(&id).cloned().unwrap_or_else(|| state.options.clone());
+            let session = match state.sessions.get(&id) {
+                Some(session) if session.shard_count == state.total => Some(session.clone()),
+                _ => None
+            };
+            let slot = match session {
+                Some(_) => Either::A(future::ok(())),
+                None => Either::B(state.ctx.queue.push_back(id))
+            };
+            let (token, info, ws) = (state.token.clone(), [id, state.total], state.ws.clone());
+            state.ctx.emit(id, LifecycleEvent::Connecting);
+            Either::B(slot.and_then(move |_| Shard::with_options(token, info, ws, options))
+                .map(move |mut shard| {
+                    if let Some(ref sender) = state.ctx.lifecycle {
+                        shard.set_lifecycle_sender(sender.clone());
+                    };
+                    match session {
+                        Some(ref session) => shard.restore_session(session),
+                        None => shard.reserve_identify()
+                    };
+                    let wrapped = ManagerShard::new(Mutex::new(shard));
+                    state.shardmap.write().insert(wrapped.lock().info[0], Arc::clone(&wrapped));
+                    state.ctx.forward_messages(&wrapped);
+                    if state.closing.load(Ordering::SeqCst) {
+                        // The manager began shutting down while this shard was connecting.
+                        tokio::spawn(wrapped.lock().disconnect(RESUME_CLOSE_CODE).map_err(|_| ()));
+                        return Loop::Break(());
+                    };
+                    state.sender.unbounded_send(wrapped).expect("Failed to send shard to stream");
+                    state.current += 1;
+
+                    if state.current == state.ids.len() {
                         Loop::Break(())
                     } else {
                         Loop::Continue(state)
                     }
-                })
-        }).map_err(|err| {
+                }))
+        })).map_err(|err| {
             error!("Failed in sharding process. {:?}", err);
         }));
 
-        (Spawner::new(rx), self.start_event_stream())
+        (Spawner::new(rx), self.start_event_stream(ctx))
     }
 
-    fn start_event_stream(&mut self) -> EventHandler {
+    fn start_event_stream(&mut self, ctx: ShardContext) -> EventHandler {
         let stream = self.message_stream.take().unwrap();
-        let (sender, receiver) = unbounded();
-        self.event_sender = Some(sender.clone());
+        let (sender, receiver) = buffer::channel(self.event_buffer.clone());
+        self.event_metrics = Some(sender.metrics_handle());
+        let shardmap = Arc::clone(&self.shards);
+        let (stop_tx, stop_rx) = oneshot::channel();
+        self.stop_events = Some(stop_tx);
+        // The event stream only stops once the manager has been shut down, not when the manager is dropped.
+        let stop = stop_rx.then(|result| match result {
+            Ok(_) => Either::A(future::ok::<(), ()>(())),
+            Err(_) => Either::B(future::empty())
+        });
 
-        tokio::spawn(stream.for_each(move |(shard, message)| {
+        let events = stream.for_each(move |(shard, connection, message)| {
             trace!("Websocket message received: {:?}", &message);
-            let event = shard.lock().resolve_packet(&message).expect("Failed to parse the shard message");
-            if let Opcodes::Dispatch = event.op {
-                sender.unbounded_send(ShardEvent {
+            // A restarted shard's old connection may still deliver messages, such as its close frame, which must not be acted upon.
+            if connection != shard.lock().connection_id() {
+                debug!("[Shard {}] Dropping a message from a previous connection.", shard.lock().info[0]);
+                return Either::A(future::ok(()));
+            };
+            if let TungsteniteMessage::Close(frame) = message {
+                let result = shard.lock().fulfill_close(frame);
+                match result {
+                    Ok(action) => ctx.handle_action(&shard, action),
+                    Err(e) => {
+                        let id = shard.lock().info[0];
+                        error!("[Shard {}] Shard has been stopped and will not reconnect. {}", id, e);
+                        shardmap.write().remove(&id);
+                        if let Error::Closed(code) = e {
+                            ctx.report(id, Error::Closed(code));
+                        };
+                        ctx.emit(id, LifecycleEvent::Failed(e));
+                    }
+                };
+                return Either::A(future::ok(()));
+            };
+            let id = shard.lock().info[0];
+            let resolved = shard.lock().resolve_packet(&message);
+            let event = match resolved {
+                Ok(Some(packet)) => packet,
+                Ok(None) => return Either::A(future::ok(())),
+                Err(e) => {
+                    ctx.report(id, e);
+                    return Either::A(future::ok(()));
+                }
+            };
+            if let Some(GatewayEvent::Unknown(ref name)) = event.t {
+                debug!("[Shard {}] Received an unknown gateway event: {}", id, name);
+            };
+            let dispatch = match event.op {
+                Opcodes::Dispatch => Some(ShardEvent {
                     packet: event.clone(),
                     shard: Arc::clone(&shard),
-                }).expect("Failed to send shard event to stream");
+                }),
+                _ => None
             };
-            let action = shard.lock().fulfill_gateway(event.clone()).expect("Failed to fufill gateway message");
-
-            match action {
-                ShardAction::Autoreconnect => {
-                    let sd = Arc::clone(&shard);
-                    tokio::spawn(shard.lock().autoreconnect().map(move |_| {
-                        info!("[Shard {}] Auto reconnection successful.", sd.lock().info[0]);
-                    }).map_err(|err| {
-                        error!("Failed to auto reconnect shard. {}", err);
-                    }));
-                },
-                ShardAction::Identify => {
-                    let info = shard.lock().info;
+            let result = shard.lock().fulfill_gateway(event);
+            match result {
+                Ok(action) => ctx.handle_action(&shard, action),
+                Err(e) => ctx.report(id, e)
+            };
+
+            // Waiting on the push applies the overflow policy, which may block further websocket reads.
+            match dispatch {
+                Some(event) => Either::B(sender.push(event)),
+                None => Either::A(future::ok(()))
+            }
+        });
+        tokio::spawn(events.select(stop).map(|_| ()).map_err(|_| ()));
+
+        EventHandler::new(receiver)
+    }
+}
+
+/// Shared state used for maintaining the connections of spawned shards.
+#[derive(Clone)]
+struct ShardContext {
+    sink_tx: Sender<ShardMessage>,
+    queue: Arc<ReconnectQueue>,
+    lifecycle: Option<UnboundedSender<ShardLifecycle>>,
+    errors: Option<UnboundedSender<ShardError>>,
+}
+
+impl ShardContext {
+    /// Emits a lifecycle event on behalf of a shard, if anything is listening for them.
+    fn emit(&self, shard_id: usize, event: LifecycleEvent) {
+        if let Some(ref sender) = self.lifecycle {
+            let _ = sender.unbounded_send(ShardLifecycle { shard_id, event });
+        };
+    }
+
+    /// Reports an error which occurred while handling a shard's message, if anything is listening for them.
+    fn report(&self, shard_id: usize, error: Error) {
+        warn!("[Shard {}] Failed to handle gateway message. {:?}", shard_id, error);
+        if let Some(ref sender) = self.errors {
+            let _ = sender.unbounded_send(ShardError { shard_id, error });
+        };
+    }
+
+    /// Forwards the messages of a shard's current websocket connection to the manager's message stream.
+    fn forward_messages(&self, shard: &ManagerShard) {
+        let stream = shard.lock().take_stream();
+        if let Some((connection, stream)) = stream {
+            let sink = MessageSink {
+                shard: Arc::clone(shard),
+                connection,
+                sender: self.sink_tx.clone(),
+            };
+            tokio::spawn(stream.map_err(MessageSinkError::from).forward(sink)
+                .map(|_| ())
+                .map_err(|e| error!("Failed to forward shard messages to the sink. {:?}", e))
+            );
+        };
+    }
+
+    /// Performs the provided action on behalf of a shard.
+    fn handle_action(&self, shard: &ManagerShard, action: ShardAction) {
+        let id = shard.lock().info[0];
+        match action {
+            ShardAction::Autoreconnect => {
+                let sd = Arc::clone(shard);
+                let ctx = self.clone();
+                let failed = self.clone();
+                tokio::spawn(shard.lock().autoreconnect().map(move |_| {
+                    ctx.forward_messages(&sd);
+                    info!("[Shard {}] Auto reconnection successful.", sd.lock().info[0]);
+                }).map_err(move |err| {
+                    error!("Failed to auto reconnect shard. {}", err);
+                    failed.emit(id, LifecycleEvent::Failed(err));
+                }));
+            },
+            ShardAction::Identify => {
+                let info = shard.lock().info;
+                let sd = Arc::clone(shard);
+                let failed = self.clone();
+                tokio::spawn(self.queue.push_back(info[0]).map(move |_| {
                     debug!("[Shard {}] Identifying with the gateway.", &info[0]);
-                    if let Err(e) = shard.lock().identify() {
+                    if let Err(e) = sd.lock().identify() {
                         warn!("[Shard {}] Failed to identify with gateway. {:?}", &info[0], e);
                     };
-                },
-                ShardAction::Reconnect => {
-                    let sd = Arc::clone(&shard);
-                    tokio::spawn(shard.lock().reconnect().map(move |_| {
-                        info!("[Shard {}] Reconnection successful.", sd.lock().info[0]);
-                    }).map_err(|err| {
-                        error!("Shard failed to reconnect to the gateway. {}", err);
-                    }));
-                },
-                ShardAction::Resume => {
-                    let sd = Arc::clone(&shard);
-                    tokio::spawn(shard.lock().resume().map(move |_| {
-                        info!("[Shard {}] Successfully resumed session.", sd.lock().info[0]);
-                    }).map_err(|err| {
-                        error!("Shard failed to resume session. {}", err);
-                    }));
-                },
-                _ => {}
+                }).map_err(move |err| {
+                    error!("[Shard {}] Failed to wait for an identify slot. {:?}", &info[0], err);
+                    failed.emit(id, LifecycleEvent::Failed(err));
+                }));
+            },
+            ShardAction::Reconnect => {
+                let sd = Arc::clone(shard);
+                let reconnecting = Arc::clone(shard);
+                let ctx = self.clone();
+                let failed = self.clone();
+                // The identify slot is obtained before reconnecting, as the shard identifies once the gateway says hello.
+                tokio::spawn(self.queue.push_back(id).and_then(move |_| {
+                    let mut shard = reconnecting.lock();
+                    shard.reserve_identify();
+                    shard.reconnect()
+                }).map(move |_| {
+                    ctx.forward_messages(&sd);
+                    info!("[Shard {}] Reconnection successful.", sd.lock().info[0]);
+                }).map_err(move |err| {
+                    error!("Shard failed to reconnect to the gateway. {}", err);
+                    failed.emit(id, LifecycleEvent::Failed(err));
+                }));
+            },
+            ShardAction::Resume => {
+                let sd = Arc::clone(shard);
+                let ctx = self.clone();
+                let failed = self.clone();
+                tokio::spawn(shard.lock().resume().map(move |_| {
+                    ctx.forward_messages(&sd);
+                    info!("[Shard {}] Successfully resumed session.", sd.lock().info[0]);
+                }).map_err(move |err| {
+                    error!("Shard failed to resume session. {}", err);
+                    failed.emit(id, LifecycleEvent::Failed(err));
+                }));
+            },
+            ShardAction::NoneAction => {}
+        };
+    }
+}
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use futures::{future, Future, Stream};
+    use tokio::{
+        runtime::current_thread::Runtime,
+        timer::Timeout
+    };
+
+    use serde_json::json;
+
+    use spectacles_model::gateway::{CloseCodes, GatewayEvent};
+
+    use crate::{
+        errors::Error,
+        mock::MockGateway,
+        shard::{LifecycleEvent, ShardLifecycle}
+    };
+
+    use super::{ErrorStream, EventHandler, LifecycleStream, ShardEvent, ShardManager, ShardStrategy, Spawner};
+
+    /// A manager with one shard, connected to a mock gateway.
+    struct Harness {
+        rt: Runtime,
+        gateway: MockGateway,
+        lifecycle: LifecycleStream,
+        errors: ErrorStream,
+        events: EventHandler,
+        _manager: ShardManager,
+        _spawner: Spawner,
+    }
+
+    impl Harness {
+        fn start() -> Self {
+            let mut rt = Runtime::new().unwrap();
+            let gateway = rt.block_on(future::lazy(|| MockGateway::start(1))).unwrap();
+            let mut manager = rt.block_on(ShardManager::with_api_base(
+                String::from("mock-token"),
+                ShardStrategy::SpawnAmount(1),
+                &gateway.api_base()
+            )).unwrap();
+            let lifecycle = manager.lifecycle();
+            let errors = manager.errors();
+            let (spawner, events) = rt.block_on(future::lazy(|| Ok::<_, ()>(manager.start_spawn()))).unwrap();
+
+            Self {
+                rt,
+                gateway,
+                lifecycle,
+                errors,
+                events,
+                _manager: manager,
+                _spawner: spawner,
+            }
+        }
+
+        /// Waits for a lifecycle event of shard 0 which matches the predicate.
+        fn wait_for(&mut self, timeout: Duration, predicate: fn(&LifecycleEvent) -> bool) {
+            let Self { ref mut rt, ref mut lifecycle, .. } = *self;
+            let found = next_matching(rt, lifecycle, timeout, |e: &ShardLifecycle| e.shard_id == 0 && predicate(&e.event));
+            assert!(found.is_some(), "Timed out waiting for a lifecycle event.");
+        }
+    }
+
+    /// Drives the runtime until the stream yields an item matching the predicate, or the timeout elapses.
+    fn next_matching<S, F>(rt: &mut Runtime, stream: &mut S, timeout: Duration, predicate: F) -> Option<S::Item>
+        where S: Stream<Error = ()>,
+              F: Fn(&S::Item) -> bool
+    {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            };
+            let remaining = deadline - now;
+            let next = (&mut *stream).into_future().map(|(item, _)| item).map_err(|(e, _)| e);
+            match rt.block_on(Timeout::new(next, remaining)) {
+                Ok(Some(item)) if predicate(&item) => return Some(item),
+                Ok(Some(_)) => {},
+                _ => return None
             };
+        }
+    }
 
-            Ok(())
-        }));
+    fn is_ready(event: &LifecycleEvent) -> bool {
+        match event {
+            LifecycleEvent::Ready => true,
+            _ => false
+        }
+    }
 
-        EventHandler::new(receiver)
+    fn is_resumed(event: &LifecycleEvent) -> bool {
+        match event {
+            LifecycleEvent::Resumed => true,
+            _ => false
+        }
     }
-}
\ No newline at end of file
+
+    fn is_authentication_failure(event: &LifecycleEvent) -> bool {
+        match event {
+            LifecycleEvent::Failed(Error::Closed(CloseCodes::AuthenticationFailed)) => true,
+            _ => false
+        }
+    }
+
+    fn is_unknown(event: &ShardEvent, name: &str) -> bool {
+        event.packet.t == Some(GatewayEvent::Unknown(name.to_string()))
+    }
+
+    #[test]
+    fn identifies_and_becomes_ready() {
+        let mut harness = Harness::start();
+        harness.wait_for(Duration::from_secs(5), is_ready);
+        assert_eq!(harness.gateway.identifies(), 1);
+        assert_eq!(harness.gateway.resumes(), 0);
+    }
+
+    #[test]
+    fn resumes_after_reconnect() {
+        let mut harness = Harness::start();
+        harness.wait_for(Duration::from_secs(5), is_ready);
+
+        // The shard only resumes once it has received a sequence number.
+        assert!(harness.gateway.dispatch(0, "MOCK_EVENT", json!({})));
+        let Harness { ref mut rt, ref mut events, .. } = harness;
+        assert!(next_matching(rt, events, Duration::from_secs(5), |e| is_unknown(e, "MOCK_EVENT")).is_some());
+
+        assert!(harness.gateway.reconnect(0));
+        harness.wait_for(Duration::from_secs(5), is_resumed);
+        assert_eq!(harness.gateway.resumes(), 1);
+        assert_eq!(harness.gateway.identifies(), 1);
+    }
+
+    #[test]
+    fn identifies_after_invalid_session() {
+        let mut harness = Harness::start();
+        harness.wait_for(Duration::from_secs(5), is_ready);
+
+        assert!(harness.gateway.invalid_session(0, false));
+        // The second identify waits for the identify delay.
+        harness.wait_for(Duration::from_secs(15), is_ready);
+        assert_eq!(harness.gateway.identifies(), 2);
+        assert_eq!(harness.gateway.resumes(), 0);
+    }
+
+    #[test]
+    fn malformed_packets_do_not_stop_events() {
+        let mut harness = Harness::start();
+        harness.wait_for(Duration::from_secs(5), is_ready);
+
+        assert!(harness.gateway.send_raw(0, "{\"op\":0,\"d\":"));
+        let Harness { ref mut rt, ref mut errors, .. } = harness;
+        let error = next_matching(rt, errors, Duration::from_secs(5), |e| e.shard_id == 0);
+        match error.map(|e| e.error) {
+            Some(Error::Json(_)) => {},
+            other => panic!("Expected a JSON error on the error stream, found {:?}", other)
+        };
+
+        assert!(harness.gateway.dispatch(0, "FUTURE_EVENT", json!({ "id": "1" })));
+        let Harness { ref mut rt, ref mut events, .. } = harness;
+        assert!(next_matching(rt, events, Duration::from_secs(5), |e| is_unknown(e, "FUTURE_EVENT")).is_some());
+    }
+
+    #[test]
+    fn fatal_close_code_fails_shard() {
+        let mut harness = Harness::start();
+        harness.wait_for(Duration::from_secs(5), is_ready);
+
+        assert!(harness.gateway.close(0, 4004));
+        harness.wait_for(Duration::from_secs(5), is_authentication_failure);
+        let Harness { ref mut rt, ref mut errors, .. } = harness;
+        let error = next_matching(rt, errors, Duration::from_secs(5), |e| e.shard_id == 0);
+        match error.map(|e| e.error) {
+            Some(Error::Closed(CloseCodes::AuthenticationFailed)) => {},
+            other => panic!("Expected the fatal close code on the error stream, found {:?}", other)
+        };
+        assert_eq!(harness.gateway.identifies(), 1);
+    }
+
+    #[test]
+    fn rejects_invalid_shard_ids() {
+        let build = |strategy| ShardManager::builder(String::from("mock-token"), strategy)
+            .gateway("wss://gateway.discord.gg", 2)
+            .build()
+            .wait();
+        let invalid = vec![
+            ShardStrategy::List { ids: vec![0, 1, 0], total: 2 },
+            ShardStrategy::List { ids: vec![2], total: 2 },
+            ShardStrategy::Range { ids: 1..3, total: 2 }
+        ];
+
+        for strategy in invalid {
+            match build(strategy) {
+                Err(Error::InvalidShardIds) => {},
+                Err(e) => panic!("Unexpected error for invalid shard IDs: {:?}", e),
+                Ok(_) => panic!("Invalid shard IDs were accepted")
+            };
+        }
+        assert!(build(ShardStrategy::Range { ids: 0..2, total: 2 }).is_ok());
+    }
+}
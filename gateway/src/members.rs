@@ -0,0 +1,274 @@
+use std::{
+    sync::Arc,
+    time::Instant
+};
+
+use futures::{
+    Async,
+    Future,
+    Poll,
+    Stream,
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        oneshot
+    }
+};
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use tokio::timer::Delay;
+
+use spectacles_model::guild::GuildMembersChunk;
+
+use crate::{
+    constants::MEMBER_CHUNK_TIMEOUT,
+    errors::{Error, Result}
+};
+
+/// The guild member requests of a shard which are awaiting chunks, keyed by nonce.
+#[derive(Default)]
+pub struct MemberRequests {
+    next_nonce: u64,
+    pending: HashMap<String, UnboundedSender<GuildMembersChunk>>,
+}
+
+impl MemberRequests {
+    /// Registers a new request, returning its nonce and the receiver for its chunks.
+    pub fn register(&mut self, shard_id: usize) -> (String, UnboundedReceiver<GuildMembersChunk>) {
+        self.next_nonce += 1;
+        let nonce = format!("{}-{}", shard_id, self.next_nonce);
+        let (tx, rx) = mpsc::unbounded();
+        self.pending.insert(nonce.clone(), tx);
+
+        (nonce, rx)
+    }
+
+    /// Whether or not any requests are awaiting chunks.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Routes a received chunk to the request that it belongs to, if the request is still pending.
+    pub fn route(&mut self, chunk: GuildMembersChunk) {
+        let nonce = match chunk.nonce {
+            Some(ref nonce) => nonce.clone(),
+            None => return
+        };
+        if let Some(sender) = self.pending.get(&nonce) {
+            if sender.unbounded_send(chunk).is_err() {
+                self.pending.remove(&nonce);
+            };
+        };
+    }
+
+    fn remove(&mut self, nonce: &str) {
+        self.pending.remove(nonce);
+    }
+}
+
+/// A stream of the guild members chunks sent in response to a guild members request.
+///
+/// The stream ends once the last chunk has been received, and fails with `Error::MemberChunkTimeout` if the next chunk does not arrive in time.
+/// The timeout only starts once the request has been sent, as it may wait behind other commands first.
+pub struct GuildMembersStream {
+    nonce: String,
+    requests: Arc<Mutex<MemberRequests>>,
+    receiver: UnboundedReceiver<GuildMembersChunk>,
+    /// Resolves once the request has been sent, or fails if the request was discarded.
+    sent: oneshot::Receiver<()>,
+    timeout: Option<Delay>,
+    finished: bool,
+}
+
+impl GuildMembersStream {
+    pub(crate) fn new(requests: Arc<Mutex<MemberRequests>>, shard_id: usize, sent: oneshot::Receiver<()>) -> Self {
+        let (nonce, receiver) = requests.lock().register(shard_id);
+
+        Self {
+            nonce,
+            requests,
+            receiver,
+            sent,
+            timeout: None,
+            finished: false
+        }
+    }
+
+    /// The nonce which identifies this request.
+    pub fn nonce(&self) -> &str {
+        &self.nonce
+    }
+
+    fn finish(&mut self) {
+        self.finished = true;
+        self.requests.lock().remove(&self.nonce);
+    }
+}
+
+impl Stream for GuildMembersStream {
+    type Item = GuildMembersChunk;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if self.finished {
+            return Ok(Async::Ready(None));
+        };
+
+        match self.receiver.poll() {
+            Ok(Async::Ready(Some(chunk))) => {
+                // Chunks without a count are the only chunk of their request.
+                if chunk.chunk_count <= chunk.chunk_index + 1 {
+                    self.finish();
+                } else {
+                    self.timeout = Some(Delay::new(Instant::now() + MEMBER_CHUNK_TIMEOUT));
+                };
+                return Ok(Async::Ready(Some(chunk)));
+            },
+            Ok(Async::Ready(None)) | Err(_) => {
+                self.finish();
+                return Ok(Async::Ready(None));
+            },
+            Ok(Async::NotReady) => {}
+        };
+
+        if self.timeout.is_none() {
+            match self.sent.poll() {
+                // A discarded request is never answered, so it times out as well.
+                Ok(Async::Ready(())) | Err(_) => self.timeout = Some(Delay::new(Instant::now() + MEMBER_CHUNK_TIMEOUT)),
+                Ok(Async::NotReady) => return Ok(Async::NotReady)
+            };
+        };
+
+        match self.timeout.as_mut().map(|timeout| timeout.poll()).unwrap_or(Ok(Async::NotReady)) {
+            Ok(Async::Ready(())) => {
+                self.finish();
+                Err(Error::MemberChunkTimeout)
+            },
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => {
+                self.finish();
+                Err(Error::from(e))
+            }
+        }
+    }
+}
+
+impl Drop for GuildMembersStream {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.requests.lock().remove(&self.nonce);
+        };
+    }
+}
+
+/// Decodes a guild members chunk and routes it to the request that it belongs to.
+/// Chunks are only decoded while a request is pending, and the requests are not locked while decoding.
+pub fn route_chunk(requests: &Mutex<MemberRequests>, data: &str) -> Result<()> {
+    if requests.lock().is_empty() {
+        return Ok(());
+    };
+    let chunk: GuildMembersChunk = serde_json::from_str(data)?;
+    requests.lock().route(chunk);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::{future, Async, Stream, sync::oneshot};
+    use parking_lot::Mutex;
+    use tokio::runtime::current_thread::Runtime;
+
+    use spectacles_model::guild::GuildMembersChunk;
+
+    use super::{route_chunk, GuildMembersStream, MemberRequests};
+
+    fn chunk(nonce: &str, index: u32, count: Option<u32>) -> GuildMembersChunk {
+        let count = count.map(|count| format!(r#","chunk_count":{}"#, count)).unwrap_or_default();
+        let json = format!(r#"{{"guild_id":"1","members":[],"chunk_index":{},"nonce":"{}"{}}}"#, index, nonce, count);
+
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn ends_after_last_chunk() {
+        let mut rt = Runtime::new().unwrap();
+        rt.block_on(future::lazy(|| {
+            // The last of several chunks, a chunk with a count of zero, and a chunk without a count.
+            for &(index, count) in &[(1, Some(2)), (0, Some(0)), (0, None)] {
+                let requests = Arc::new(Mutex::new(MemberRequests::default()));
+                let (sent_tx, sent_rx) = oneshot::channel();
+                let mut stream = GuildMembersStream::new(requests.clone(), 0, sent_rx);
+                sent_tx.send(()).unwrap();
+
+                requests.lock().route(chunk(stream.nonce(), index, count));
+                assert!(match stream.poll() {
+                    Ok(Async::Ready(Some(_))) => true,
+                    _ => false
+                });
+                assert!(match stream.poll() {
+                    Ok(Async::Ready(None)) => true,
+                    _ => false
+                });
+                assert!(requests.lock().is_empty());
+            }
+
+            Ok::<_, ()>(())
+        })).unwrap();
+    }
+
+    #[test]
+    fn times_out_once_sent() {
+        let mut rt = Runtime::new().unwrap();
+        rt.block_on(future::lazy(|| {
+            let requests = Arc::new(Mutex::new(MemberRequests::default()));
+            let (sent_tx, sent_rx) = oneshot::channel();
+            let mut stream = GuildMembersStream::new(requests, 0, sent_rx);
+
+            assert!(match stream.poll() {
+                Ok(Async::NotReady) => true,
+                _ => false
+            });
+            assert!(stream.timeout.is_none());
+
+            sent_tx.send(()).unwrap();
+            assert!(match stream.poll() {
+                Ok(Async::NotReady) => true,
+                _ => false
+            });
+            assert!(stream.timeout.is_some());
+
+            Ok::<_, ()>(())
+        })).unwrap();
+    }
+
+    #[test]
+    fn routes_chunks_by_nonce() {
+        let requests = Arc::new(Mutex::new(MemberRequests::default()));
+        // Chunks are not decoded while no request is waiting for them.
+        assert!(route_chunk(&requests, "{").is_ok());
+
+        let mut rt = Runtime::new().unwrap();
+        rt.block_on(future::lazy(|| {
+            let (_sent_tx, sent_rx) = oneshot::channel();
+            let mut stream = GuildMembersStream::new(requests.clone(), 0, sent_rx);
+            let other = format!(r#"{{"guild_id":"1","members":[],"chunk_index":0,"chunk_count":1,"nonce":"{}-other"}}"#, stream.nonce());
+            route_chunk(&requests, &other).unwrap();
+            assert!(route_chunk(&requests, "{").is_err());
+            assert!(match stream.poll() {
+                Ok(Async::NotReady) => true,
+                _ => false
+            });
+
+            let json = format!(r#"{{"guild_id":"1","members":[],"chunk_index":0,"chunk_count":1,"nonce":"{}"}}"#, stream.nonce());
+            route_chunk(&requests, &json).unwrap();
+            assert!(match stream.poll() {
+                Ok(Async::Ready(Some(chunk))) => chunk.nonce.as_ref().map(String::as_str) == Some(stream.nonce()),
+                _ => false
+            });
+
+            Ok::<_, ()>(())
+        })).unwrap();
+    }
+}
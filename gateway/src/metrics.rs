@@ -0,0 +1,133 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    time::{Duration, Instant}
+};
+
+use hashbrown::HashMap;
+
+use spectacles_model::gateway::GatewayEvent;
+
+/// A snapshot of the health metrics of a shard.
+#[derive(Debug, Clone, Default)]
+pub struct ShardMetrics {
+    /// The round-trip time of the shard's last acknowledged heartbeat, if any.
+    pub latency: Option<Duration>,
+    /// The amount of dispatch events received by the shard, keyed by event type.
+    pub events: HashMap<GatewayEvent, u64>,
+    /// The amount of times the shard has identified.
+    pub identifies: u64,
+    /// The amount of times the shard has attempted to resume its session.
+    pub resumes: u64,
+    /// The amount of times the shard has reconnected to the gateway.
+    pub reconnects: u64,
+    /// The time at which the shard last received a dispatch event, if any.
+    pub last_dispatch: Option<Instant>,
+}
+
+impl ShardMetrics {
+    /// Records a dispatch event received by the shard.
+    pub(crate) fn record_dispatch(&mut self, event: Option<&GatewayEvent>) {
+        if let Some(event) = event {
+            *self.events.entry(event.clone()).or_insert(0) += 1;
+        };
+        self.last_dispatch = Some(Instant::now());
+    }
+}
+
+/// Encodes the provided shard metrics, keyed by shard ID, in the Prometheus text format.
+pub(crate) fn encode_prometheus(metrics: &HashMap<usize, ShardMetrics>) -> String {
+    let mut shards: Vec<(&usize, &ShardMetrics)> = metrics.iter().collect();
+    shards.sort_by_key(|(id, _)| **id);
+    let now = Instant::now();
+    let mut out = String::new();
+
+    write_header(&mut out, "spectacles_shard_latency_seconds", "gauge", "The round-trip time of the shard's last acknowledged heartbeat.");
+    for (id, shard) in &shards {
+        if let Some(latency) = shard.latency {
+            let _ = writeln!(out, "spectacles_shard_latency_seconds{{shard=\"{}\"}} {}", id, seconds(latency));
+        };
+    }
+
+    write_header(&mut out, "spectacles_shard_events_total", "counter", "The amount of dispatch events received by the shard.");
+    for (id, shard) in &shards {
+        // Unknown events are grouped together, so that their names cannot create an unbounded amount of series.
+        let mut events: BTreeMap<&str, u64> = BTreeMap::new();
+        for (event, count) in &shard.events {
+            let name = match event {
+                GatewayEvent::Unknown(_) => "UNKNOWN",
+                event => event.as_str()
+            };
+            *events.entry(name).or_insert(0) += count;
+        }
+        for (event, count) in events {
+            let _ = writeln!(out, "spectacles_shard_events_total{{shard=\"{}\",event=\"{}\"}} {}", id, escape(event), count);
+        }
+    }
+
+    let counters: [(&str, &str, fn(&ShardMetrics) -> u64); 3] = [
+        ("spectacles_shard_identifies_total", "The amount of times the shard has identified.", |m| m.identifies),
+        ("spectacles_shard_resumes_total", "The amount of times the shard has attempted to resume its session.", |m| m.resumes),
+        ("spectacles_shard_reconnects_total", "The amount of times the shard has reconnected to the gateway.", |m| m.reconnects),
+    ];
+    for (name, help, value) in counters.iter() {
+        write_header(&mut out, name, "counter", help);
+        for (id, shard) in &shards {
+            let _ = writeln!(out, "{}{{shard=\"{}\"}} {}", name, id, value(shard));
+        }
+    }
+
+    write_header(&mut out, "spectacles_shard_last_dispatch_age_seconds", "gauge", "The time since the shard last received a dispatch event.");
+    for (id, shard) in &shards {
+        if let Some(last) = shard.last_dispatch {
+            let _ = writeln!(out, "spectacles_shard_last_dispatch_age_seconds{{shard=\"{}\"}} {}", id, seconds(now - last));
+        };
+    }
+
+    out
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+/// Escapes a label value, as required by the text exposition format.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use hashbrown::HashMap;
+
+    use spectacles_model::gateway::GatewayEvent;
+
+    use super::{encode_prometheus, escape, ShardMetrics};
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape("a\\b\"c\nd"), "a\\\\b\\\"c\\nd");
+    }
+
+    #[test]
+    fn groups_unknown_events() {
+        let mut shard = ShardMetrics::default();
+        shard.record_dispatch(Some(&GatewayEvent::MESSAGE_CREATE));
+        shard.record_dispatch(Some(&GatewayEvent::Unknown(String::from("FUTURE_EVENT"))));
+        shard.record_dispatch(Some(&GatewayEvent::Unknown(String::from("\"}\n"))));
+        let mut metrics = HashMap::new();
+        metrics.insert(0, shard);
+
+        let encoded = encode_prometheus(&metrics);
+        assert!(encoded.contains("spectacles_shard_events_total{shard=\"0\",event=\"MESSAGE_CREATE\"} 1\n"));
+        assert!(encoded.contains("spectacles_shard_events_total{shard=\"0\",event=\"UNKNOWN\"} 2\n"));
+        assert!(!encoded.contains("FUTURE_EVENT"));
+    }
+}
@@ -0,0 +1,336 @@
+//! A local websocket server which speaks the Discord gateway protocol, for testing shards without connecting to Discord.
+//!
+//! The mock gateway sends Hello to every connection, answers heartbeats, and answers identifies and resumes with READY and RESUMED.
+//! Dispatches, invalid sessions, reconnects and close codes can then be sent to connected shards.
+//! Payloads are JSON encoded, and transport compression is not supported.
+
+use std::{
+    net::SocketAddr,
+    sync::Arc
+};
+
+use futures::{
+    Future,
+    Sink,
+    Stream,
+    sync::mpsc::{self, UnboundedSender}
+};
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tokio::{
+    io,
+    net::{TcpListener, TcpStream}
+};
+use tokio_tungstenite::{
+    accept_async,
+    tungstenite::{
+        Error as TungsteniteError,
+        protocol::{
+            CloseFrame,
+            frame::coding::CloseCode,
+            Message
+        }
+    }
+};
+
+use crate::errors::Result;
+
+/// The heartbeat interval sent to every connection, in milliseconds.
+const HEARTBEAT_INTERVAL: u64 = 41250;
+
+/// A local gateway server, along with an API server which answers `/gateway/bot` requests.
+///
+/// A [`ShardManager`] may be pointed at the mock gateway with [`ShardManager::with_api_base`], using [`api_base()`].
+///
+/// [`ShardManager`]: ../struct.ShardManager.html
+/// [`ShardManager::with_api_base`]: ../struct.ShardManager.html#method.with_api_base
+/// [`api_base()`]: #method.api_base
+pub struct MockGateway {
+    /// The address of the websocket server.
+    pub ws_addr: SocketAddr,
+    /// The address of the API server.
+    pub api_addr: SocketAddr,
+    state: Arc<Mutex<MockState>>
+}
+
+#[derive(Default)]
+struct MockState {
+    next_connection: usize,
+    connections: HashMap<usize, MockConnection>,
+    sessions: HashMap<String, usize>,
+    identifies: usize,
+    resumes: usize,
+}
+
+struct MockConnection {
+    sender: UnboundedSender<Message>,
+    shard_id: Option<usize>,
+    seq: u64
+}
+
+impl MockGateway {
+    /// Starts a mock gateway on local ports chosen by the OS, which recommends the provided amount of shards.
+    /// This must be called from within a Tokio runtime.
+    pub fn start(shard_count: usize) -> Result<Self> {
+        let localhost: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let ws = TcpListener::bind(&localhost)?;
+        let api = TcpListener::bind(&localhost)?;
+        let ws_addr = ws.local_addr()?;
+        let api_addr = api.local_addr()?;
+        let state = Arc::new(Mutex::new(MockState::default()));
+
+        let ws_state = Arc::clone(&state);
+        tokio::spawn(ws.incoming()
+            .map_err(|err| error!("[Mock] Failed to accept websocket connection. {:?}", err))
+            .for_each(move |socket| {
+                tokio::spawn(MockGateway::accept(Arc::clone(&ws_state), socket));
+                Ok(())
+            })
+        );
+
+        let body = json!({
+            "url": format!("ws://{}", ws_addr),
+            "shards": shard_count,
+            "session_start_limit": {
+                "total": 1000,
+                "remaining": 1000,
+                "reset_after": 0,
+                "max_concurrency": shard_count.max(1)
+            }
+        }).to_string();
+        tokio::spawn(api.incoming()
+            .map_err(|err| error!("[Mock] Failed to accept API connection. {:?}", err))
+            .for_each(move |socket| {
+                tokio::spawn(MockGateway::respond(socket, body.clone()));
+                Ok(())
+            })
+        );
+
+        Ok(Self {
+            ws_addr,
+            api_addr,
+            state
+        })
+    }
+
+    /// The websocket URL of the mock gateway.
+    pub fn ws_url(&self) -> String {
+        format!("ws://{}", self.ws_addr)
+    }
+
+    /// The base URL of the mock API, which may be used in place of Discord's.
+    pub fn api_base(&self) -> String {
+        format!("http://{}", self.api_addr)
+    }
+
+    /// The amount of identifies that the mock gateway has received.
+    pub fn identifies(&self) -> usize {
+        self.state.lock().identifies
+    }
+
+    /// The amount of resumes that the mock gateway has received.
+    pub fn resumes(&self) -> usize {
+        self.state.lock().resumes
+    }
+
+    /// Sends a dispatch event to the provided shard, returning whether the shard is connected.
+    pub fn dispatch(&self, shard_id: usize, event: &str, data: Value) -> bool {
+        let mut state = self.state.lock();
+        match state.connection_for(shard_id) {
+            Some(id) => state.dispatch(id, event, data),
+            None => false
+        }
+    }
+
+    /// Sends an Invalid Session payload to the provided shard, returning whether the shard is connected.
+    pub fn invalid_session(&self, shard_id: usize, resumable: bool) -> bool {
+        self.send_to(shard_id, json!({ "op": 9, "d": resumable, "s": null, "t": null }))
+    }
+
+    /// Sends a Reconnect payload to the provided shard, returning whether the shard is connected.
+    pub fn reconnect(&self, shard_id: usize) -> bool {
+        self.send_to(shard_id, json!({ "op": 7, "d": null, "s": null, "t": null }))
+    }
+
+    /// Closes the provided shard's connection with a close code, returning whether the shard is connected.
+    pub fn close(&self, shard_id: usize, code: u16) -> bool {
+        let state = self.state.lock();
+        let frame = CloseFrame {
+            code: CloseCode::from(code),
+            reason: "".into()
+        };
+
+        state.connection_for(shard_id)
+            .and_then(|id| state.connections.get(&id))
+            .map(|conn| conn.sender.unbounded_send(Message::Close(Some(frame))).is_ok())
+            .unwrap_or(false)
+    }
+
+    /// Sends a raw text message to the provided shard, such as a malformed payload, returning whether the shard is connected.
+    pub fn send_raw(&self, shard_id: usize, text: &str) -> bool {
+        let state = self.state.lock();
+
+        state.connection_for(shard_id)
+            .and_then(|id| state.connections.get(&id))
+            .map(|conn| conn.sender.unbounded_send(Message::text(text)).is_ok())
+            .unwrap_or(false)
+    }
+
+    fn send_to(&self, shard_id: usize, payload: Value) -> bool {
+        let state = self.state.lock();
+        match state.connection_for(shard_id) {
+            Some(id) => state.send(id, payload),
+            None => false
+        }
+    }
+
+    fn accept(state: Arc<Mutex<MockState>>, socket: TcpStream) -> impl Future<Item = (), Error = ()> {
+        accept_async(socket)
+            .map_err(|err| warn!("[Mock] Failed to complete websocket handshake. {:?}", err))
+            .and_then(move |ws| {
+                let (sink, stream) = ws.split();
+                let (tx, rx) = mpsc::unbounded();
+                tokio::spawn(rx.map_err(|_| {
+                    TungsteniteError::Io(io::Error::new(io::ErrorKind::Other, "Error whilst attempting to select sink."))
+                }).forward(sink).map(|_| ()).map_err(|_| ()));
+
+                let id = {
+                    let mut state = state.lock();
+                    state.next_connection += 1;
+                    let id = state.next_connection;
+                    state.connections.insert(id, MockConnection {
+                        sender: tx,
+                        shard_id: None,
+                        seq: 0
+                    });
+                    state.send(id, json!({
+                        "op": 10,
+                        "d": { "heartbeat_interval": HEARTBEAT_INTERVAL },
+                        "s": null,
+                        "t": null
+                    }));
+
+                    id
+                };
+
+                let stream_state = Arc::clone(&state);
+                stream.for_each(move |message| {
+                    stream_state.lock().handle(id, message);
+                    Ok(())
+                }).then(move |_| {
+                    state.lock().connections.remove(&id);
+                    Ok::<(), ()>(())
+                })
+            })
+    }
+
+    /// Answers an API request with the gateway information, regardless of the requested route.
+    fn respond(socket: TcpStream, body: String) -> impl Future<Item = (), Error = ()> {
+        io::read(socket, vec![0; 4096])
+            .and_then(move |(socket, _, _)| {
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                io::write_all(socket, response.into_bytes())
+            })
+            .map(|_| ())
+            .map_err(|err| warn!("[Mock] Failed to answer API request. {:?}", err))
+    }
+}
+
+impl MockState {
+    /// Finds the most recent connection which belongs to the provided shard.
+    fn connection_for(&self, shard_id: usize) -> Option<usize> {
+        self.connections.iter()
+            .filter(|(_, conn)| conn.shard_id == Some(shard_id))
+            .map(|(id, _)| *id)
+            .max()
+    }
+
+    fn send(&self, id: usize, payload: Value) -> bool {
+        self.connections.get(&id)
+            .map(|conn| conn.sender.unbounded_send(Message::text(payload.to_string())).is_ok())
+            .unwrap_or(false)
+    }
+
+    fn dispatch(&mut self, id: usize, event: &str, data: Value) -> bool {
+        match self.connections.get_mut(&id) {
+            Some(conn) => {
+                conn.seq += 1;
+                let payload = json!({ "op": 0, "d": data, "s": conn.seq, "t": event });
+                conn.sender.unbounded_send(Message::text(payload.to_string())).is_ok()
+            },
+            None => false
+        }
+    }
+
+    fn handle(&mut self, id: usize, message: Message) {
+        let payload: Value = match message {
+            Message::Text(text) => match serde_json::from_str(&text) {
+                Ok(payload) => payload,
+                Err(_) => return
+            },
+            _ => return
+        };
+
+        match payload["op"].as_u64() {
+            // Heartbeat
+            Some(1) => {
+                self.send(id, json!({ "op": 11, "d": null, "s": null, "t": null }));
+            },
+            // Identify
+            Some(2) => {
+                self.identifies += 1;
+                let shard = [
+                    payload["d"]["shard"][0].as_u64().unwrap_or(0),
+                    payload["d"]["shard"][1].as_u64().unwrap_or(1)
+                ];
+                let session_id = format!("mock-session-{}", self.identifies);
+                self.sessions.insert(session_id.clone(), shard[0] as usize);
+                if let Some(conn) = self.connections.get_mut(&id) {
+                    conn.shard_id = Some(shard[0] as usize);
+                    conn.seq = 0;
+                };
+
+                self.dispatch(id, "READY", json!({
+                    "v": 6,
+                    "user": {
+                        "id": "1",
+                        "username": "Mock",
+                        "discriminator": "0001",
+                        "avatar": null,
+                        "bot": true
+                    },
+                    "private_channels": [],
+                    "guilds": [],
+                    "session_id": session_id,
+                    "_trace": ["mock-gateway"],
+                    "shard": shard
+                }));
+            },
+            // Resume
+            Some(6) => {
+                let shard_id = payload["d"]["session_id"].as_str()
+                    .and_then(|session_id| self.sessions.get(session_id))
+                    .cloned();
+                match shard_id {
+                    Some(shard_id) => {
+                        self.resumes += 1;
+                        if let Some(conn) = self.connections.get_mut(&id) {
+                            conn.shard_id = Some(shard_id);
+                            conn.seq = payload["d"]["seq"].as_u64().unwrap_or(0);
+                        };
+                        self.dispatch(id, "RESUMED", json!({ "_trace": ["mock-gateway"] }));
+                    },
+                    None => {
+                        self.send(id, json!({ "op": 9, "d": false, "s": null, "t": null }));
+                    }
+                };
+            },
+            _ => {}
+        };
+    }
+}
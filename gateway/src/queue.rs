@@ -1,17 +1,35 @@
-use std::sync::Arc;
+use std::{
+    cmp,
+    sync::Arc,
+    time::{Duration, Instant}
+};
 
-use futures::{AsyncSink, Future, Poll, Sink, StartSend, sync::mpsc::{SendError, UnboundedSender}};
+use futures::{AsyncSink, Future, Poll, Sink, StartSend, sync::mpsc::{SendError, Sender}};
+use futures::future::{self, Either, Loop};
 use parking_lot::Mutex;
+use redis::r#async::SharedConnection;
+use tokio::timer::Delay;
 use tokio_tungstenite::tungstenite::{
     Error as TungsteniteError,
     Message as TungsteniteMessage,
 };
 
-use crate::Shard;
+use spectacles_model::gateway::SessionStartLimit;
+
+use crate::{
+    constants::{IDENTIFY_DELAY, SESSION_START_RESET},
+    errors::Error,
+    Shard
+};
+
+/// A shard's message, tagged with the ID of the connection it was received on.
+pub type ShardMessage = (Arc<Mutex<Shard>>, usize, TungsteniteMessage);
 
 pub struct MessageSink {
     pub shard: Arc<Mutex<Shard>>,
-    pub sender: UnboundedSender<(Arc<Mutex<Shard>>, TungsteniteMessage)>,
+    /// The ID of the connection whose messages are being forwarded.
+    pub connection: usize,
+    pub sender: Sender<ShardMessage>,
 }
 
 impl Sink for MessageSink {
@@ -19,8 +37,8 @@ impl Sink for MessageSink {
     type SinkError = MessageSinkError;
 
     fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
-        Ok(match self.sender.start_send((self.shard.clone(), item))? {
-            AsyncSink::NotReady((_, item)) => AsyncSink::NotReady(item),
+        Ok(match self.sender.start_send((self.shard.clone(), self.connection, item))? {
+            AsyncSink::NotReady((_, _, item)) => AsyncSink::NotReady(item),
             AsyncSink::Ready => AsyncSink::Ready,
         })
     }
@@ -36,13 +54,13 @@ impl Sink for MessageSink {
 }
 
 pub enum MessageSinkError {
-    MpscSend(SendError<(Arc<Mutex<Shard>>, TungsteniteMessage)>),
+    MpscSend(SendError<ShardMessage>),
     Tungstenite(TungsteniteError),
 }
 
 
-impl From<SendError<(Arc<Mutex<Shard>>, TungsteniteMessage)>> for MessageSinkError {
-    fn from(e: SendError<(Arc<Mutex<Shard>>, TungsteniteMessage)>) -> Self {
+impl From<SendError<ShardMessage>> for MessageSinkError {
+    fn from(e: SendError<ShardMessage>) -> Self {
         MessageSinkError::MpscSend(e)
     }
 }
@@ -64,34 +82,164 @@ impl ::std::fmt::Debug for MessageSinkError {
     }
 }
 
-pub trait ReconnectQueue {
-    type Error: 'static;
-    fn push_back(&mut self, shard_id: usize) -> Box<Future<Item = (), Error = Self::Error> + Send>;
-    fn pop_front(&mut self) -> Box<Future<Item = Option<usize>, Error = Self::Error> + Send>;
+/// A queue which determines when shards are allowed to identify with the gateway.
+///
+/// Discord only allows one identify per concurrency bucket every five seconds, across every process using the same token.
+/// Queues may be shared between shard managers, so that several clusters can identify under one bot token.
+pub trait ReconnectQueue: Send + Sync {
+    /// Places the provided shard at the back of the queue.
+    /// The returned future resolves once the shard is allowed to identify.
+    fn push_back(&self, shard_id: usize) -> Box<Future<Item = (), Error = Error> + Send>;
 }
 
-/*#[derive(Clone)]
-pub struct ShardQueue {
-    pub queue: VecDeque<usize>,
+/// Reserves an identify slot, returning the amount of milliseconds to wait if the session start limit or the concurrency bucket is exhausted.
+/// The session start count is created from the provided arguments if it does not exist, and expires when the limit resets.
+const IDENTIFY_SCRIPT: &str = r"
+redis.call('SET', KEYS[1], ARGV[1], 'NX', 'PX', ARGV[2])
+local remaining = tonumber(redis.call('GET', KEYS[1]))
+local wait
+if remaining and remaining <= 0 then
+    wait = redis.call('PTTL', KEYS[1])
+elseif redis.call('SET', KEYS[2], ARGV[4], 'NX', 'PX', ARGV[3]) then
+    if remaining then
+        redis.call('DECR', KEYS[1])
+    end
+    return 0
+else
+    wait = redis.call('PTTL', KEYS[2])
+end
+if wait < 50 then
+    wait = 50
+end
+return wait
+";
+
+/// An identify queue backed by Redis, which allows several processes to share one identify window and session start limit.
+///
+/// The remaining session starts are kept in Redis, and each concurrency bucket is a key which expires once the bucket may identify again.
+/// Every process using the same bot token should use the same key, and create the queue from the same session start limit.
+#[derive(Clone)]
+pub struct RedisQueue {
+    /// The underlying Redis connection.
+    pub conn: SharedConnection,
+    key: String,
+    total: i32,
+    remaining: i32,
+    reset_at: Instant,
+    max_concurrency: usize,
 }
 
-impl ShardQueue {
-    pub fn new(shard_count: usize) -> Self {
+impl RedisQueue {
+    /// Creates a new Redis queue with the provided connection and key prefix, from the session start limit returned by Discord.
+    pub fn new(conn: SharedConnection, key: &str, limit: &SessionStartLimit) -> Self {
+        let reset_after = Duration::from_millis(cmp::max(limit.reset_after, 0) as u64);
+
         Self {
-            queue: VecDeque::with_capacity(shard_count)
+            conn,
+            key: key.to_string(),
+            total: limit.total,
+            remaining: limit.remaining,
+            reset_at: Instant::now() + reset_after,
+            max_concurrency: cmp::max(limit.max_concurrency, 1),
         }
     }
+
+    /// The session starts to store if none are stored yet, along with the amount of milliseconds until they reset.
+    fn session_starts(&self) -> (i32, u64) {
+        let now = Instant::now();
+        let (remaining, reset_after) = if now < self.reset_at {
+            (self.remaining, self.reset_at - now)
+        } else {
+            (self.total, SESSION_START_RESET)
+        };
+
+        (remaining, cmp::max(reset_after.as_secs() * 1000 + u64::from(reset_after.subsec_millis()), 1))
+    }
+}
+
+impl ReconnectQueue for RedisQueue {
+    fn push_back(&self, shard_id: usize) -> Box<Future<Item = (), Error = Error> + Send> {
+        let queue = self.clone();
+        let sessions = format!("{}:sessions", self.key);
+        let bucket = format!("{}:{}", self.key, shard_id % self.max_concurrency);
+        let window = IDENTIFY_DELAY.as_secs() * 1000;
+
+        Box::new(future::loop_fn((), move |_| {
+            let (remaining, reset_after) = queue.session_starts();
+            redis::cmd("EVAL").arg(IDENTIFY_SCRIPT).arg(2).arg(&sessions).arg(&bucket)
+                .arg(remaining).arg(reset_after).arg(window).arg(shard_id)
+                .query_async::<_, i64>(queue.conn.clone())
+                .from_err()
+                .and_then(move |(_, wait)| {
+                    if wait <= 0 {
+                        return Either::A(future::ok(Loop::Break(())));
+                    };
+                    debug!("[Shard {}] Identify slot is taken, waiting {}ms.", shard_id, wait);
+                    Either::B(Delay::new(Instant::now() + Duration::from_millis(wait as u64))
+                        .from_err()
+                        .map(|_| Loop::Continue(()))
+                    )
+                })
+        }))
+    }
 }
 
-impl ReconnectQueue for ShardQueue {
-    type Error = ();
-    fn push_back(&mut self, shard_id: usize) -> Box<Future<Item = (), Error = Self::Error> + Send> {
-        self.queue.push_back(shard_id);
-        Box::new(future::ok(()))
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    use redis::{Client, r#async::SharedConnection};
+    use tokio::runtime::current_thread::Runtime;
+
+    use spectacles_model::gateway::SessionStartLimit;
+
+    use crate::constants::IDENTIFY_DELAY;
+
+    use super::{ReconnectQueue, RedisQueue};
+
+    /// Connects to a Redis server on localhost, returning a key prefix which is unique to the test.
+    fn connect(rt: &mut Runtime) -> (SharedConnection, String) {
+        let client = Client::open("redis://127.0.0.1/").unwrap();
+        let conn = rt.block_on(client.get_shared_async_connection()).unwrap();
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().subsec_nanos();
+
+        (conn, format!("spectacles-test:identify:{}", nanos))
+    }
+
+    fn limit(remaining: i32, reset_after: i32) -> SessionStartLimit {
+        SessionStartLimit {
+            total: 1000,
+            remaining,
+            reset_after,
+            max_concurrency: 1
+        }
+    }
+
+    /// Requires a Redis server on localhost, run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn shared_queues_space_identifies() {
+        let mut rt = Runtime::new().unwrap();
+        let (conn, key) = connect(&mut rt);
+        let first = RedisQueue::new(conn.clone(), &key, &limit(1000, 60_000));
+        let second = RedisQueue::new(conn, &key, &limit(1000, 60_000));
+
+        rt.block_on(first.push_back(0)).unwrap();
+        let released = Instant::now();
+        rt.block_on(second.push_back(1)).unwrap();
+        assert!(released.elapsed() >= IDENTIFY_DELAY - Duration::from_millis(50));
     }
 
-    fn pop_front(&mut self) -> Box<Future<Item = Option<usize>, Error = Self::Error> + Send> {
-        Box::new(future::ok(self.queue.pop_front()))
+    /// Requires a Redis server on localhost, run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn waits_for_session_start_reset() {
+        let mut rt = Runtime::new().unwrap();
+        let (conn, key) = connect(&mut rt);
+        let queue = RedisQueue::new(conn, &key, &limit(0, 1500));
+
+        let start = Instant::now();
+        rt.block_on(queue.push_back(0)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(1400));
     }
 }
-*/
\ No newline at end of file
@@ -0,0 +1,230 @@
+use std::{
+    io::ErrorKind,
+    path::PathBuf
+};
+
+use futures::{future, Future};
+use hashbrown::HashMap;
+use redis::r#async::SharedConnection;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::errors::Error;
+
+/// The information required to resume a shard's session.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionInfo {
+    /// The ID of the shard which the session belongs to.
+    pub shard_id: usize,
+    /// The total amount of shards that the shard identified with.
+    pub shard_count: usize,
+    /// The ID of the session.
+    pub session_id: String,
+    /// The last sequence number received by the shard.
+    pub seq: u64,
+}
+
+/// A store which persists shard sessions, so that shards may resume them after the process restarts.
+pub trait SessionStore: Send + Sync {
+    /// Loads the saved sessions, keyed by shard ID.
+    fn load(&self) -> Box<Future<Item = HashMap<usize, SessionInfo>, Error = Error> + Send>;
+    /// Saves the provided sessions, replacing any sessions saved for the same shards.
+    fn save(&self, sessions: Vec<SessionInfo>) -> Box<Future<Item = (), Error = Error> + Send>;
+    /// Removes the sessions saved for the provided shards.
+    fn remove(&self, shard_ids: Vec<usize>) -> Box<Future<Item = (), Error = Error> + Send>;
+}
+
+/// A session store which keeps sessions in a JSON file.
+///
+/// Saving and removing sessions reads the file and writes it back without any locking,
+/// so processes which share the file must not save or remove sessions at the same time.
+/// Use a separate file for each process, or a `RedisSessionStore`, if their shards start or shut down together.
+#[derive(Debug, Clone)]
+pub struct FileSessionStore {
+    path: PathBuf,
+}
+
+impl FileSessionStore {
+    /// Creates a new file session store, which reads and writes the provided path.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Loads the saved sessions, applies the provided changes to them, and writes them back.
+    fn modify<F>(&self, change: F) -> Box<Future<Item = (), Error = Error> + Send>
+        where F: FnOnce(&mut HashMap<usize, SessionInfo>) + Send + 'static
+    {
+        let path = self.path.clone();
+        Box::new(self.load().and_then(move |mut saved| {
+            change(&mut saved);
+            let sessions: Vec<SessionInfo> = saved.into_iter().map(|(_, s)| s).collect();
+
+            future::result(serde_json::to_vec(&sessions))
+                .from_err()
+                .and_then(move |json| tokio_fs::write(path, json).from_err())
+                .map(|_| ())
+        }))
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn load(&self) -> Box<Future<Item = HashMap<usize, SessionInfo>, Error = Error> + Send> {
+        Box::new(tokio_fs::read(self.path.clone()).then(|result| -> Result<HashMap<usize, SessionInfo>, Error> {
+            match result {
+                Ok(bytes) => {
+                    let sessions: Vec<SessionInfo> = serde_json::from_slice(&bytes)?;
+                    Ok(sessions.into_iter().map(|s| (s.shard_id, s)).collect())
+                },
+                Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(HashMap::new()),
+                Err(e) => Err(Error::from(e))
+            }
+        }))
+    }
+
+    fn save(&self, sessions: Vec<SessionInfo>) -> Box<Future<Item = (), Error = Error> + Send> {
+        // Sessions saved by other processes for different shards are kept.
+        self.modify(move |saved| {
+            for session in sessions {
+                saved.insert(session.shard_id, session);
+            }
+        })
+    }
+
+    fn remove(&self, shard_ids: Vec<usize>) -> Box<Future<Item = (), Error = Error> + Send> {
+        self.modify(move |saved| {
+            for shard_id in shard_ids {
+                saved.remove(&shard_id);
+            }
+        })
+    }
+}
+
+/// A session store which keeps sessions in a Redis hash, keyed by shard ID.
+#[derive(Clone)]
+pub struct RedisSessionStore {
+    /// The underlying Redis connection.
+    pub conn: SharedConnection,
+    key: String,
+}
+
+impl RedisSessionStore {
+    /// Creates a new Redis session store with the provided connection and hash key.
+    pub fn new(conn: SharedConnection, key: &str) -> Self {
+        Self {
+            conn,
+            key: key.to_string(),
+        }
+    }
+}
+
+impl SessionStore for RedisSessionStore {
+    fn load(&self) -> Box<Future<Item = HashMap<usize, SessionInfo>, Error = Error> + Send> {
+        Box::new(redis::cmd("HGETALL").arg(&self.key)
+            .query_async::<_, Vec<(usize, String)>>(self.conn.clone())
+            .from_err()
+            .and_then(|(_, entries)| -> Result<HashMap<usize, SessionInfo>, Error> {
+                let mut sessions = HashMap::new();
+                for (shard_id, json) in entries {
+                    sessions.insert(shard_id, serde_json::from_str(&json)?);
+                }
+
+                Ok(sessions)
+            })
+        )
+    }
+
+    fn save(&self, sessions: Vec<SessionInfo>) -> Box<Future<Item = (), Error = Error> + Send> {
+        if sessions.is_empty() {
+            return Box::new(future::ok(()));
+        };
+        let mut cmd = redis::cmd("HSET");
+        cmd.arg(&self.key);
+        for session in &sessions {
+            match serde_json::to_string(session) {
+                Ok(json) => cmd.arg(session.shard_id).arg(json),
+                Err(e) => return Box::new(future::err(Error::from(e)))
+            };
+        }
+
+        Box::new(cmd.query_async::<_, ()>(self.conn.clone())
+            .from_err()
+            .map(|_| ())
+        )
+    }
+
+    fn remove(&self, shard_ids: Vec<usize>) -> Box<Future<Item = (), Error = Error> + Send> {
+        if shard_ids.is_empty() {
+            return Box::new(future::ok(()));
+        };
+
+        Box::new(redis::cmd("HDEL").arg(&self.key).arg(shard_ids)
+            .query_async::<_, ()>(self.conn.clone())
+            .from_err()
+            .map(|_| ())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        time::{SystemTime, UNIX_EPOCH}
+    };
+
+    use redis::Client;
+    use tokio::runtime::Runtime;
+
+    use super::{FileSessionStore, RedisSessionStore, SessionInfo, SessionStore};
+
+    fn session(shard_id: usize) -> SessionInfo {
+        SessionInfo {
+            shard_id,
+            shard_count: 2,
+            session_id: format!("session-{}", shard_id),
+            seq: 1,
+        }
+    }
+
+    #[test]
+    fn file_store_removes_sessions() {
+        let path = env::temp_dir().join(format!("spectacles-sessions-{}.json", std::process::id()));
+        let store = FileSessionStore::new(path.clone());
+        let mut rt = Runtime::new().unwrap();
+
+        rt.block_on(store.save(vec![session(0), session(1)])).unwrap();
+        rt.block_on(store.remove(vec![0])).unwrap();
+        let sessions = rt.block_on(store.load()).unwrap();
+        let _ = std::fs::remove_file(path);
+
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[&1].session_id, "session-1");
+    }
+
+    /// Requires a Redis server on localhost, run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn redis_store_round_trip() {
+        let mut rt = Runtime::new().unwrap();
+        let client = Client::open("redis://127.0.0.1/").unwrap();
+        let conn = rt.block_on(client.get_shared_async_connection()).unwrap();
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().subsec_nanos();
+        let store = RedisSessionStore::new(conn, &format!("spectacles-test:sessions:{}", nanos));
+
+        rt.block_on(store.save(vec![session(0), session(12)])).unwrap();
+        let sessions = rt.block_on(store.load()).unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[&12].shard_id, 12);
+        assert_eq!(sessions[&12].session_id, "session-12");
+
+        let mut resumed = session(12);
+        resumed.seq = 40;
+        rt.block_on(store.save(vec![resumed])).unwrap();
+        rt.block_on(store.remove(vec![0])).unwrap();
+        let sessions = rt.block_on(store.load()).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[&12].seq, 40);
+
+        rt.block_on(store.remove(vec![12])).unwrap();
+        assert!(rt.block_on(store.load()).unwrap().is_empty());
+    }
+}
@@ -1,27 +1,37 @@
 use std::{
     io::{Error as IoError, ErrorKind},
     str::FromStr,
-    sync::Arc,
+    sync::{Arc, atomic::{AtomicUsize, Ordering}},
     time::{Duration, Instant}
 };
 
 use futures::{
-    future::Future,
+    Async,
+    future::{self, Either, Future, Loop},
+    Poll,
     Sink,
     stream::{SplitStream, Stream},
-    sync::mpsc::{self, UnboundedSender}
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        oneshot
+    }
 };
 use native_tls::TlsConnector;
 use parking_lot::Mutex;
 use tokio::net::TcpStream as TokioTcpStream;
-use tokio::timer::Interval;
+use tokio::timer::{Delay, Interval, Timeout};
 use tokio_dns::TcpStream;
 use tokio_tls::TlsStream;
 use tokio_tungstenite::{
     tungstenite::{
         Error as TungsteniteError,
         handshake::client::Request,
-        protocol::{Message as WebsocketMessage, WebSocketConfig},
+        protocol::{
+            CloseFrame,
+            frame::coding::CloseCode,
+            Message as WebsocketMessage,
+            WebSocketConfig
+        },
     },
     WebSocketStream
 };
@@ -29,15 +39,19 @@ use tokio_tungstenite::stream::Stream as TungsteniteStream;
 use url::Url;
 
 use spectacles_model::{
+    etf,
     gateway::{
+        CloseCodes,
         GatewayEvent,
         HeartbeatPacket,
         HelloPacket,
         IdentifyPacket,
         IdentifyProperties,
+        Intents,
         Opcodes,
         ReadyPacket,
         ReceivePacket,
+        RequestGuildMembers,
         ResumeSessionPacket,
         SendablePacket,
     },
@@ -45,12 +59,52 @@ use spectacles_model::{
 };
 
 use crate::{
-    constants::GATEWAY_VERSION,
+    commands::CommandLimiter,
+    compression::ZlibStream,
+    members::{self, GuildMembersStream, MemberRequests},
+    metrics::ShardMetrics,
+    session::SessionInfo,
+    constants::{CLOSE_TIMEOUT, GATEWAY_VERSION},
     errors::{Error, Result}
 };
 
 pub type ShardSplitStream = SplitStream<WebSocketStream<TungsteniteStream<TokioTcpStream, TlsStream<TokioTcpStream>>>>;
 
+/// The incoming message stream of a shard's websocket connection.
+///
+/// The stream may be closed locally by the shard, in which case the underlying socket is dropped and a close message is yielded.
+pub struct ShardStream {
+    inner: Option<ShardSplitStream>,
+    control: UnboundedReceiver<WebsocketMessage>,
+    /// Dropped alongside the stream, which notifies the shard that the connection has wound down.
+    _closed: oneshot::Sender<()>,
+}
+
+/// The handles to a newly established websocket connection.
+struct Connection {
+    sender: UnboundedSender<WebsocketMessage>,
+    control: UnboundedSender<WebsocketMessage>,
+    closed: oneshot::Receiver<()>,
+    stream: ShardStream,
+}
+
+impl Stream for ShardStream {
+    type Item = WebsocketMessage;
+    type Error = TungsteniteError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if let Ok(Async::Ready(Some(message))) = self.control.poll() {
+            self.inner = None;
+            return Ok(Async::Ready(Some(message)));
+        };
+
+        match self.inner.as_mut() {
+            Some(stream) => stream.poll(),
+            None => Ok(Async::Ready(None))
+        }
+    }
+}
+
 /// A Spectacles Gateway shard.
 #[derive(Clone)]
 pub struct Shard {
@@ -67,13 +121,135 @@ pub struct Shard {
     /// The channel which is used to send websocket messages.
     pub sender: Arc<Mutex<UnboundedSender<WebsocketMessage>>>,
     /// The shard's message stream, which is used to receive messages.
-    pub stream: Arc<Mutex<Option<ShardSplitStream>>>,
-    /// Used to determine whether or not the shard is currently in a state of connecting.
-    current_state: Arc<Mutex<String>>,
+    pub stream: Arc<Mutex<Option<ShardStream>>>,
+    /// The ID of the shard's current websocket connection, which is incremented every time the shard connects.
+    connection: Arc<AtomicUsize>,
+    /// The guild member requests which are awaiting chunks.
+    member_requests: Arc<Mutex<MemberRequests>>,
+    /// Limits the rate at which commands are sent over the shard's connection.
+    commands: Arc<Mutex<CommandLimiter>>,
+    /// The channel which is used to close the shard's message stream locally.
+    control: Arc<Mutex<UnboundedSender<WebsocketMessage>>>,
+    /// Resolves once the message stream of the shard's current connection has been dropped.
+    closed: Arc<Mutex<Option<oneshot::Receiver<()>>>>,
+    /// The health metrics collected for this shard.
+    metrics: Arc<Mutex<ShardMetrics>>,
+    /// The current state of the shard's connection.
+    current_state: Arc<Mutex<ShardState>>,
+    /// The channel which lifecycle events are emitted to, if any.
+    lifecycle: Option<UnboundedSender<ShardLifecycle>>,
     /// This shard's current heartbeat.
     pub heartbeat: Arc<Mutex<Heartbeat>>,
     /// The URL of the Discord Gateway.
-    ws_uri: String
+    ws_uri: String,
+    /// The connection options that this shard was created with.
+    pub options: ShardOptions,
+    /// The inflate context for this shard's connection, if transport compression is enabled.
+    inflater: Arc<Mutex<Option<ZlibStream>>>,
+    /// Whether or not an identify slot was obtained before connecting, in which case the shard identifies as soon as the gateway says hello.
+    identify_reserved: bool
+}
+
+/// Options which determine how a shard connects to the gateway.
+#[derive(Debug, Clone)]
+pub struct ShardOptions {
+    /// Whether or not to request `zlib-stream` transport compression from the gateway.
+    pub compress: bool,
+    /// The encoding that the gateway will use for payloads.
+    pub encoding: Encoding,
+    /// The version of the gateway to connect to.
+    pub version: u8,
+    /// The gateway intents to identify with, if any.
+    pub intents: Option<Intents>,
+    /// The total number of members at which the gateway stops sending offline members of a guild.
+    pub large_threshold: i32,
+    /// The TLS connector used to connect to the gateway, or `None` to use the system defaults.
+    pub tls_connector: Option<TlsConnector>
+}
+
+impl Default for ShardOptions {
+    fn default() -> Self {
+        Self {
+            compress: false,
+            encoding: Encoding::default(),
+            version: GATEWAY_VERSION,
+            intents: None,
+            large_threshold: 250,
+            tls_connector: None
+        }
+    }
+}
+
+/// The payload encodings supported by the Discord gateway.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Encoding {
+    /// Payloads are sent as JSON text frames.
+    Json,
+    /// Payloads are sent as binary Erlang Term Format frames.
+    Etf
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Json
+    }
+}
+
+impl Encoding {
+    /// The value of the `encoding` query parameter for this encoding.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Json => "json",
+            Encoding::Etf => "etf"
+        }
+    }
+}
+
+/// The state of a shard's connection to the gateway.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ShardState {
+    /// The shard is establishing a websocket connection.
+    Connecting,
+    /// The shard is connected, and is waiting for the gateway's hello.
+    Handshake,
+    /// The shard has identified, and is waiting for the ready event.
+    Identifying,
+    /// The shard is resuming its previous session.
+    Resuming,
+    /// The shard's session is active.
+    Connected,
+    /// The gateway closed the shard's connection.
+    Disconnected,
+    /// The shard's connection was closed locally, and will not be reconnected.
+    Closed
+}
+
+/// A change in the lifecycle of a shard.
+#[derive(Debug)]
+pub enum LifecycleEvent {
+    /// The shard is establishing a websocket connection.
+    Connecting,
+    /// The shard has sent an identify payload.
+    Identifying,
+    /// The shard received the ready event, and its session is active.
+    Ready,
+    /// The shard has resumed its previous session.
+    Resumed,
+    /// The shard's connection was closed, with the provided close code if there was one.
+    Disconnected(Option<u16>),
+    /// The shard is reconnecting to the gateway.
+    Reconnecting,
+    /// The shard has stopped, and will not reconnect.
+    Failed(Error)
+}
+
+/// A lifecycle event, along with the ID of the shard that it belongs to.
+#[derive(Debug)]
+pub struct ShardLifecycle {
+    /// The ID of the shard which emitted this event.
+    pub shard_id: usize,
+    /// The lifecycle event.
+    pub event: LifecycleEvent,
 }
 
 /// Various actions that a shard can perform.
@@ -89,77 +265,167 @@ pub enum ShardAction {
 pub struct Heartbeat {
     pub acknowledged: bool,
     pub seq: u64,
+    /// The time at which the last heartbeat was sent.
+    pub last_sent: Option<Instant>,
+    /// The time at which the last heartbeat acknowledgement was received.
+    pub last_ack: Option<Instant>,
+    /// Identifies the currently running heartbeat interval, so that superseded intervals stop.
+    generation: u64,
 }
 
 impl Heartbeat {
     fn new() -> Heartbeat {
         Self {
             acknowledged: false,
-            seq: 0
+            seq: 0,
+            last_sent: None,
+            last_ack: None,
+            generation: 0
+        }
+    }
+
+    /// The round-trip time of the last acknowledged heartbeat, if any.
+    pub fn latency(&self) -> Option<Duration> {
+        match (self.last_sent, self.last_ack) {
+            (Some(sent), Some(ack)) if self.acknowledged && ack >= sent => Some(ack - sent),
+            _ => None
         }
     }
+
+    /// Resets the heartbeat for a new connection.
+    /// The sequence is kept, so that the session may be resumed on the new connection.
+    /// Only starting a new session, which happens when the shard reconnects without resuming, resets the sequence.
+    fn reset(&mut self) {
+        *self = Heartbeat {
+            seq: self.seq,
+            generation: self.generation,
+            ..Heartbeat::new()
+        };
+    }
 }
 
 impl Shard {
     /// Creates a new Discord Shard, with the provided token.
     pub fn new(token: String, info: [usize; 2], ws_uri: String) -> impl Future<Item=Shard, Error=Error> {
-        Shard::begin_connection(&ws_uri, info[0])
-            .map(move |(sender, stream)| {
-                Shard {
-                    token,
-                    session_id: None,
-                    presence: ClientPresence {
-                        status: String::from("online"),
-                        ..Default::default()
-                    },
-                    info,
-                    interval: None,
-                    sender: Arc::new(Mutex::new(sender)),
-                    current_state: Arc::new(Mutex::new(String::from("handshake"))),
-                    stream: Arc::new(Mutex::new(Some(stream))),
-                    heartbeat: Arc::new(Mutex::new(Heartbeat::new())),
-                    ws_uri
-                }
-            })
+        Shard::with_options(token, info, ws_uri, ShardOptions::default())
+    }
+
+    /// Creates a new Discord Shard, with the provided token and connection options.
+    pub fn with_options(token: String, info: [usize; 2], ws_uri: String, options: ShardOptions) -> impl Future<Item=Shard, Error=Error> {
+        Shard::begin_connection(&ws_uri, info[0], &options)
+            .map(move |conn| Shard::from_connection(token, info, ws_uri, options, conn))
+    }
+
+    fn from_connection(token: String, info: [usize; 2], ws_uri: String, options: ShardOptions, conn: Connection) -> Shard {
+        let inflater = if options.compress {
+            Some(ZlibStream::new())
+        } else {
+            None
+        };
+
+        Shard {
+            token,
+            session_id: None,
+            presence: ClientPresence {
+                status: String::from("online"),
+                ..Default::default()
+            },
+            info,
+            interval: None,
+            sender: Arc::new(Mutex::new(conn.sender)),
+            current_state: Arc::new(Mutex::new(ShardState::Handshake)),
+            metrics: Arc::new(Mutex::new(ShardMetrics::default())),
+            lifecycle: None,
+            stream: Arc::new(Mutex::new(Some(conn.stream))),
+            connection: Arc::new(AtomicUsize::new(0)),
+            control: Arc::new(Mutex::new(conn.control)),
+            closed: Arc::new(Mutex::new(Some(conn.closed))),
+            commands: Arc::new(Mutex::new(CommandLimiter::new())),
+            member_requests: Arc::new(Mutex::new(MemberRequests::default())),
+            heartbeat: Arc::new(Mutex::new(Heartbeat::new())),
+            ws_uri,
+            options,
+            inflater: Arc::new(Mutex::new(inflater)),
+            identify_reserved: false
+        }
+    }
+
+    /// The ID of the shard's current websocket connection.
+    /// Messages which were received on an earlier connection of the shard will not have this ID.
+    pub fn connection_id(&self) -> usize {
+        self.connection.load(Ordering::SeqCst)
+    }
+
+    /// Takes the message stream of the shard's current connection, along with the ID of that connection.
+    pub(crate) fn take_stream(&self) -> Option<(usize, ShardStream)> {
+        let mut stream = self.stream.lock();
+        let connection = self.connection_id();
+
+        stream.take().map(|stream| (connection, stream))
     }
 
     pub fn fulfill_gateway(&mut self, packet: ReceivePacket) -> Result<ShardAction> {
         let info = self.info.clone();
-        let current_state = self.current_state.lock().clone();
+        let current_state = *self.current_state.lock();
         match packet.op {
             Opcodes::Dispatch => {
-                if let Some(GatewayEvent::READY) = packet.t {
-                    let ready: ReadyPacket = serde_json::from_str(packet.d.get()).unwrap();
-                    *self.current_state.lock() = "connected".to_string();
-                    self.session_id = Some(ready.session_id.clone());
-                    trace!("[Shard {}] Received ready, set session ID as {}", &info[0], ready.session_id)
+                if let Some(seq) = packet.s {
+                    self.heartbeat.lock().seq = seq;
+                };
+                self.metrics.lock().record_dispatch(packet.t.as_ref());
+                match packet.t {
+                    Some(GatewayEvent::READY) => {
+                        let ready: ReadyPacket = serde_json::from_str(packet.d.get())?;
+                        *self.current_state.lock() = ShardState::Connected;
+                        self.session_id = Some(ready.session_id.clone());
+                        trace!("[Shard {}] Received ready, set session ID as {}", &info[0], ready.session_id);
+                        self.emit(LifecycleEvent::Ready);
+                    },
+                    Some(GatewayEvent::RESUMED) => {
+                        *self.current_state.lock() = ShardState::Connected;
+                        self.emit(LifecycleEvent::Resumed);
+                    },
+                    Some(GatewayEvent::GUILD_MEMBERS_CHUNK) => members::route_chunk(&self.member_requests, packet.d.get())?,
+                    _ => {}
                 };
                 Ok(ShardAction::NoneAction)
             }
             Opcodes::Hello => {
-                if self.current_state.lock().clone() == "resume".to_string() {
-                    return Ok(ShardAction::NoneAction)
-                };
-                let hello: HelloPacket = serde_json::from_str(packet.d.get()).unwrap();
+                let hello: HelloPacket = serde_json::from_str(packet.d.get())?;
                 if hello.heartbeat_interval > 0 {
                     self.interval = Some(hello.heartbeat_interval);
-                }
-                if current_state == "handshake".to_string() {
                     let dur = Duration::from_millis(hello.heartbeat_interval);
                     tokio::spawn(Shard::begin_interval(self.clone(), dur));
-                    return Ok(ShardAction::Identify);
                 }
-                Ok(ShardAction::Autoreconnect)
+                match current_state {
+                    ShardState::Resuming => Ok(ShardAction::NoneAction),
+                    // A session restored from a previous process is resumed on the first connection.
+                    ShardState::Handshake if self.session().is_some() => {
+                        self.send_resume()?;
+                        Ok(ShardAction::NoneAction)
+                    },
+                    ShardState::Handshake if self.identify_reserved => {
+                        self.identify_reserved = false;
+                        self.identify()?;
+                        Ok(ShardAction::NoneAction)
+                    },
+                    ShardState::Handshake => Ok(ShardAction::Identify),
+                    _ => Ok(ShardAction::Autoreconnect)
+                }
             },
             Opcodes::HeartbeatAck => {
-                let mut hb = self.heartbeat.lock().clone();
+                let mut hb = self.heartbeat.lock();
                 hb.acknowledged = true;
+                hb.last_ack = Some(Instant::now());
                 Ok(ShardAction::NoneAction)
             },
-            Opcodes::Reconnect => Ok(ShardAction::Reconnect),
+            // The gateway asks for a reconnect when the connection is being moved, so the session may be resumed.
+            Opcodes::Reconnect => Ok(ShardAction::Autoreconnect),
             Opcodes::InvalidSession => {
-                let invalid: bool = serde_json::from_str(packet.d.get()).unwrap();
+                let invalid: bool = serde_json::from_str(packet.d.get())?;
                 if !invalid {
+                    self.session_id = None;
+                    self.heartbeat.lock().seq = 0;
                     Ok(ShardAction::Identify)
                 } else { Ok(ShardAction::Resume) }
             },
@@ -167,18 +433,36 @@ impl Shard {
         }
     }
 
+    /// Determines the action to take after the gateway has closed this shard's connection.
+    /// Fatal close codes, from which the shard cannot recover, are returned as an error.
+    pub fn fulfill_close(&mut self, frame: Option<CloseFrame>) -> Result<ShardAction> {
+        let code = frame.map(|f| u16::from(f.code));
+        self.emit(LifecycleEvent::Disconnected(code));
+        if *self.current_state.lock() == ShardState::Closed {
+            debug!("[Shard {}] Connection has been closed locally, and will not be reconnected.", self.info[0]);
+            return Ok(ShardAction::NoneAction);
+        };
+        *self.current_state.lock() = ShardState::Disconnected;
+
+        close_action(self.info[0], code)
+    }
+
     /// Identifies a shard with Discord.
     pub fn identify(&mut self) -> Result<()> {
         let token = self.token.clone();
         let shard = self.info.clone();
         let presence = self.presence.clone();
+        *self.current_state.lock() = ShardState::Identifying;
+        self.metrics.lock().identifies += 1;
+        self.emit(LifecycleEvent::Identifying);
         self.send_payload(IdentifyPacket {
-            large_threshold: 250,
+            large_threshold: self.options.large_threshold,
             token,
             shard,
             compress: false,
             presence: Some(presence),
-            version: GATEWAY_VERSION,
+            version: self.options.version,
+            intents: self.options.intents,
             properties: IdentifyProperties {
                 os: std::env::consts::OS.to_string(),
                 browser: String::from("spectacles-rs"),
@@ -199,6 +483,8 @@ impl Shard {
     /// Makes a request to reconnect the shard.
     pub fn reconnect(&mut self) -> impl Future<Item = (), Error = Error> + Send {
         debug!("[Shard {}] Attempting to reconnect to gateway.", &self.info[0]);
+        self.metrics.lock().reconnects += 1;
+        self.emit(LifecycleEvent::Reconnecting);
         self.reset_values().expect("[Shard] Failed to reset this shard for autoreconnecting.");
         self.dial_gateway()
     }
@@ -206,39 +492,156 @@ impl Shard {
     /// Resumes a shard's past session.
     pub fn resume(&mut self) -> impl Future<Item = (), Error = Error> + Send {
         debug!("[Shard {}] Attempting to resume gateway connection.", &self.info[0]);
+        self.metrics.lock().reconnects += 1;
+        self.emit(LifecycleEvent::Reconnecting);
+        let shard = self.clone();
+
+        self.dial_gateway().and_then(move |_| shard.send_resume())
+    }
+
+    /// Obtains a snapshot of this shard's health metrics.
+    pub fn metrics(&self) -> ShardMetrics {
+        ShardMetrics {
+            latency: self.heartbeat.lock().latency(),
+            ..self.metrics.lock().clone()
+        }
+    }
+
+    /// Restores a session which was saved by a previous process.
+    /// The shard will attempt to resume this session once the gateway says hello, and identify if the session is no longer valid.
+    pub fn restore_session(&mut self, session: &SessionInfo) {
+        debug!("[Shard {}] Restoring saved session {}.", self.info[0], session.session_id);
+        self.session_id = Some(session.session_id.clone());
+        self.heartbeat.lock().seq = session.seq;
+    }
+
+    /// Marks the shard as having obtained an identify slot before connecting.
+    /// The shard will identify as soon as the gateway says hello, instead of waiting for another slot.
+    pub(crate) fn reserve_identify(&mut self) {
+        self.identify_reserved = true;
+    }
+
+    /// The information required to resume this shard's session, if it has one.
+    pub fn session(&self) -> Option<SessionInfo> {
         let seq = self.heartbeat.lock().seq;
-        let token = self.token.clone();
-        let state = self.current_state.clone();
-        let session = self.session_id.clone();
-        let sender = self.sender.clone();
-
-        self.dial_gateway().then(move |result|{
-            if result.is_err() { return result };
-            *state.lock() = "resuming".to_string();
-            let payload = ResumeSessionPacket {
-                session_id: session.unwrap(),
-                seq,
-                token
+        match self.session_id {
+            Some(ref session_id) if seq > 0 => Some(SessionInfo {
+                shard_id: self.info[0],
+                shard_count: self.info[1],
+                session_id: session_id.clone(),
+                seq
+            }),
+            _ => None
+        }
+    }
+
+    /// Closes the shard's connection with the provided close code, and stops its heartbeat interval.
+    /// Closing with code 1000 or 1001 invalidates the session, while any other code keeps it, so that the shard may resume later.
+    /// The returned future resolves once the connection's message stream has wound down.
+    pub fn disconnect(&mut self, code: u16) -> Box<Future<Item = (), Error = Error> + Send> {
+        debug!("[Shard {}] Disconnecting from the gateway with close code {}.", self.info[0], code);
+        *self.current_state.lock() = ShardState::Closed;
+        {
+            let mut hb = self.heartbeat.lock();
+            hb.generation += 1;
+            if code == 1000 || code == 1001 {
+                hb.seq = 0;
+                self.session_id = None;
             };
+        }
 
-            send(&sender, WebsocketMessage::text(payload.to_json()?))
-        })
+        let frame = CloseFrame {
+            code: CloseCode::from(code),
+            reason: "".into()
+        };
+        if let Err(e) = send(&self.sender, WebsocketMessage::Close(Some(frame))) {
+            debug!("[Shard {}] Failed to send close frame, the connection has already closed. {:?}", self.info[0], e);
+        };
+
+        let info = self.info;
+        let control = self.control.clone();
+        let closed = self.closed.lock().take();
+        match closed {
+            Some(rx) => Box::new(Timeout::new(rx, CLOSE_TIMEOUT).then(move |result| {
+                if let Err(ref e) = result {
+                    if e.is_elapsed() {
+                        warn!("[Shard {}] Gateway did not acknowledge the close frame, dropping the connection.", info[0]);
+                        let _ = send(&control, WebsocketMessage::Close(None));
+                    };
+                };
+                Ok(())
+            })),
+            None => Box::new(future::ok(()))
+        }
+    }
+
+    /// The current state of the shard's connection.
+    pub fn state(&self) -> ShardState {
+        *self.current_state.lock()
     }
+
+    /// Sets the channel which this shard will emit lifecycle events to.
+    pub fn set_lifecycle_sender(&mut self, sender: UnboundedSender<ShardLifecycle>) {
+        self.lifecycle = Some(sender);
+    }
+
     /// Resolves a Websocket message into a ReceivePacket struct.
-    pub fn resolve_packet(&self, mess: &WebsocketMessage) -> Result<ReceivePacket> {
+    /// If transport compression is enabled, binary frames are buffered until a complete message has been received.
+    /// Binary messages are decoded as ETF if the shard is using the ETF encoding.
+    pub fn resolve_packet(&self, mess: &WebsocketMessage) -> Result<Option<ReceivePacket>> {
         match mess {
-            WebsocketMessage::Binary(v) => serde_json::from_slice(v),
-            WebsocketMessage::Text(v) => serde_json::from_str(v),
-            _ => unreachable!("Invalid type detected."),
-        }.map_err(Error::from)
+            WebsocketMessage::Binary(v) => {
+                let mut inflater = self.inflater.lock();
+                let bytes = match inflater.as_mut() {
+                    Some(zlib) => zlib.push(v)?,
+                    None => Some(v.clone())
+                };
+
+                match bytes {
+                    Some(ref b) if self.options.encoding == Encoding::Etf => Ok(Some(etf::from_slice(b)?)),
+                    Some(b) => Ok(Some(serde_json::from_slice(&b)?)),
+                    None => Ok(None)
+                }
+            },
+            WebsocketMessage::Text(v) => Ok(Some(serde_json::from_str(v)?)),
+            // Pings are answered by the websocket itself, and close frames are handled by `fulfill_close()`.
+            _ => Ok(None),
+        }
     }
 
     /// Sends a payload to the Discord Gateway.
+    /// Commands are rate limited per connection, so the payload may be queued and sent later.
     pub fn send_payload<T: SendablePacket>(&self, payload: T) -> Result<()> {
-        let json = payload.to_json()?;
-        send(&self.sender, WebsocketMessage::text(json))
+        self.send_command(payload, None)
     }
 
+    /// Sends a command through the command limiter, notifying the provided sender once the command may be sent.
+    fn send_command<T: SendablePacket>(&self, payload: T, sent: Option<oneshot::Sender<()>>) -> Result<()> {
+        let message = encode_payload(self.options.encoding, payload)?;
+        let mut commands = self.commands.lock();
+        if let Some(message) = commands.push_notify(message, sent) {
+            return send(&self.sender, message);
+        };
+        if commands.start_draining() {
+            debug!("[Shard {}] Command limit reached, queueing {} commands.", self.info[0], commands.pending());
+            tokio::spawn(Shard::drain_commands(self.commands.clone(), self.sender.clone(), self.info[0]));
+        };
+
+        Ok(())
+    }
+
+
+    /// Requests the members of a guild, returning a stream of the guild members chunks sent in response.
+    /// The stream ends once the last chunk has been received, and times out if chunks stop arriving after the request has been sent.
+    pub fn request_guild_members(&self, mut request: RequestGuildMembers) -> Result<GuildMembersStream> {
+        let (sent_tx, sent_rx) = oneshot::channel();
+        let stream = GuildMembersStream::new(self.member_requests.clone(), self.info[0], sent_rx);
+        request.nonce = Some(stream.nonce().to_string());
+        debug!("[Shard {}] Requesting guild members for guild {}.", self.info[0], request.guild_id.0);
+        self.send_command(request, Some(sent_tx))?;
+
+        Ok(stream)
+    }
 
     /// Change the status of the current shard.
     pub fn change_status(&mut self, status: Status) -> Result<()> {
@@ -262,9 +665,11 @@ impl Shard {
         Ok(())
     }
 
+    /// Clears the shard's session and sequence, before identifying a new session.
+    /// Connections which resume the session keep both, as only the heartbeat is reset for them.
     fn reset_values(&mut self) -> Result<()> {
         self.session_id = None;
-        *self.current_state.lock() = "disconnected".to_string();
+        *self.current_state.lock() = ShardState::Disconnected;
 
         let mut hb = self.heartbeat.lock();
         hb.acknowledged = true;
@@ -273,38 +678,131 @@ impl Shard {
         Ok(())
     }
 
+    fn send_resume(&self) -> Result<()> {
+        let payload = ResumeSessionPacket {
+            session_id: self.session_id.clone().unwrap_or_default(),
+            seq: self.heartbeat.lock().seq,
+            token: self.token.clone()
+        };
+        *self.current_state.lock() = ShardState::Resuming;
+        self.metrics.lock().resumes += 1;
+        self.commands.lock().record();
+
+        send(&self.sender, encode_payload(self.options.encoding, payload)?)
+    }
+
+    fn emit(&self, event: LifecycleEvent) {
+        if let Some(ref sender) = self.lifecycle {
+            let _ = sender.unbounded_send(ShardLifecycle {
+                shard_id: self.info[0],
+                event
+            });
+        };
+    }
+
     fn heartbeat(&mut self) -> Result<()> {
         debug!("[Shard {}] Sending heartbeat.", self.info[0]);
-        let seq = self.heartbeat.lock().seq;
+        let seq = {
+            let mut hb = self.heartbeat.lock();
+            hb.acknowledged = false;
+            hb.last_sent = Some(Instant::now());
+            hb.seq
+        };
+
+        // Heartbeats use the slots reserved for them, rather than waiting behind other commands.
+        self.commands.lock().record();
+        send(&self.sender, encode_payload(self.options.encoding, HeartbeatPacket { seq })?)
+    }
+
+    fn drain_commands(
+        commands: Arc<Mutex<CommandLimiter>>,
+        sender: Arc<Mutex<UnboundedSender<WebsocketMessage>>>,
+        shard_id: usize
+    ) -> impl Future<Item = (), Error = ()> {
+        future::loop_fn((), move |_| {
+            let mut limiter = commands.lock();
+            loop {
+                match limiter.next_pending() {
+                    Some(Ok(message)) => {
+                        if let Err(e) = send(&sender, message) {
+                            warn!("[Shard {}] Failed to send queued command. {:?}", shard_id, e);
+                        };
+                    },
+                    Some(Err(wait)) => {
+                        return Either::A(Delay::new(Instant::now() + wait)
+                            .map(|_| Loop::Continue(()))
+                            .map_err(move |e| error!("[Shard {}] Failed to wait for the command limit. {:?}", shard_id, e)));
+                    },
+                    None => return Either::B(future::ok(Loop::Break(())))
+                };
+            }
+        })
+    }
 
-        self.send_payload(HeartbeatPacket { seq })
+    /// Drops the shard's current websocket connection.
+    /// The message stream yields a close message without a close code, which causes the shard to reconnect.
+    fn drop_connection(&self) -> Result<()> {
+        send(&self.control, WebsocketMessage::Close(None))
     }
 
     fn dial_gateway(&mut self) -> impl Future<Item = (), Error = Error> + Send {
         let info = self.info.clone();
-        *self.current_state.lock() = String::from("connected");
+        *self.current_state.lock() = ShardState::Connecting;
+        self.emit(LifecycleEvent::Connecting);
         let state = self.current_state.clone();
         let orig_sender = self.sender.clone();
         let orig_stream = self.stream.clone();
+        let connection = self.connection.clone();
+        let orig_control = self.control.clone();
+        let orig_closed = self.closed.clone();
+        let commands = self.commands.clone();
         let heartbeat = self.heartbeat.clone();
+        let inflater = self.inflater.clone();
 
-        Shard::begin_connection(&self.ws_uri, info[0])
-            .map(move |(sender, stream)| {
-                *orig_sender.lock() = sender;
-                *heartbeat.lock() = Heartbeat::new();
-                *state.lock() = String::from("handshake");
-                *orig_stream.lock() = Some(stream);
+        Shard::begin_connection(&self.ws_uri, info[0], &self.options)
+            .map(move |conn| {
+                *orig_sender.lock() = conn.sender;
+                *orig_control.lock() = conn.control;
+                *orig_closed.lock() = Some(conn.closed);
+                commands.lock().reset();
+                let mut inflater = inflater.lock();
+                if inflater.is_some() {
+                    *inflater = Some(ZlibStream::new());
+                };
+                heartbeat.lock().reset();
+                *state.lock() = ShardState::Handshake;
+                // The connection ID is changed while the stream is locked, so that it is always taken along with its ID.
+                let mut stream = orig_stream.lock();
+                connection.fetch_add(1, Ordering::SeqCst);
+                *stream = Some(conn.stream);
             })
     }
 
 
     fn begin_interval(mut shard: Shard, duration: Duration) -> impl Future<Item = (), Error = ()> {
         let info = shard.info.clone();
+        let generation = {
+            let mut hb = shard.heartbeat.lock();
+            hb.generation += 1;
+            hb.generation
+        };
+
         Interval::new(Instant::now(), duration)
             .map_err(move |err| {
                 warn!("[Shard {}] Failed to begin heartbeat interval. {:?}", info[0], err);
             })
             .for_each(move |_| {
+                let hb = *shard.heartbeat.lock();
+                if hb.generation != generation {
+                    return Err(());
+                };
+                if hb.last_sent.is_some() && !hb.acknowledged {
+                    warn!("[Shard {}] The last heartbeat was not acknowledged, dropping the zombie connection.", info[0]);
+                    if let Err(r) = shard.drop_connection() {
+                        error!("[Shard {}] Failed to drop the zombie connection. {:?}", info[0], r);
+                    };
+                    return Err(());
+                };
                 if let Err(r) = shard.heartbeat() {
                     warn!("[Shard {}] Failed to perform heartbeat. {:?}", info[0], r);
                     return Err(());
@@ -313,19 +811,40 @@ impl Shard {
             })
     }
 
-    fn begin_connection(ws: &str, shard_id: usize) -> impl Future<Item = (UnboundedSender<WebsocketMessage>, ShardSplitStream), Error = Error> {
-        let url = Url::from_str(ws).expect("Invalid Websocket URL has been provided.");
+    fn begin_connection(ws: &str, shard_id: usize, options: &ShardOptions) -> impl Future<Item = Connection, Error = Error> {
+        let mut url = match Url::from_str(ws) {
+            Ok(url) => url,
+            Err(_) => return Either::A(future::err(Error::InvalidGatewayUrl(ws.to_string())))
+        };
+        url.query_pairs_mut()
+            .append_pair("v", &options.version.to_string())
+            .append_pair("encoding", options.encoding.as_str());
+        if options.compress {
+            url.query_pairs_mut().append_pair("compress", "zlib-stream");
+        };
+        // Plain websocket URLs, such as those of a local mock gateway, are connected to without TLS.
+        let secure = url.scheme() != "ws";
         let req = Request::from(url);
-        let (host, port) = Shard::get_addr_info(&req);
-        let tlsconn = TlsConnector::new().unwrap();
-        let tlsconn = tokio_tls::TlsConnector::from(tlsconn);
+        let (host, port) = match Shard::get_addr_info(&req) {
+            Ok(info) => info,
+            Err(e) => return Either::A(future::err(e))
+        };
+        let tlsconn = options.tls_connector.clone();
 
         let socket = TcpStream::connect((host.as_ref(), port));
         let handshake = socket.and_then(move |socket| {
             debug!("[Shard {}] Beginning handshake with gateway.", shard_id);
-            tlsconn.connect(host.as_ref(), socket)
+            if !secure {
+                return Either::A(future::ok(TungsteniteStream::Plain(socket)));
+            };
+            let tlsconn = match tlsconn.map_or_else(TlsConnector::new, Ok) {
+                Ok(conn) => tokio_tls::TlsConnector::from(conn),
+                Err(e) => return Either::B(Either::A(future::err(std::io::Error::new(std::io::ErrorKind::Other, e))))
+            };
+
+            Either::B(Either::B(tlsconn.connect(host.as_ref(), socket)
                 .map(|s| TungsteniteStream::Tls(s))
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))))
         });
         let stream = handshake.and_then(|mut stream| {
             tokio_tungstenite::stream::NoDelay::set_nodelay(&mut stream, true)
@@ -339,7 +858,7 @@ impl Shard {
             })).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
         });
 
-        stream.map(move |(wstream, _)| {
+        Either::B(stream.map(move |(wstream, _)| {
             let (tx, rx) = mpsc::unbounded();
             let (sink, stream) = wstream.split();
             tokio::spawn(rx.map_err(|err| {
@@ -347,15 +866,64 @@ impl Shard {
                 TungsteniteError::Io(IoError::new(ErrorKind::Other, "Error whilst attempting to select sink."))
             }).forward(sink).map(|_| ()).map_err(|_| ()));
 
-            (tx, stream)
-        }).from_err()
+            let (control_tx, control_rx) = mpsc::unbounded();
+            let (closed_tx, closed_rx) = oneshot::channel();
+            let stream = ShardStream {
+                inner: Some(stream),
+                control: control_rx,
+                _closed: closed_tx,
+            };
+
+            Connection {
+                sender: tx,
+                control: control_tx,
+                closed: closed_rx,
+                stream
+            }
+        }).from_err())
+    }
+
+    fn get_addr_info(req: &Request) -> Result<(String, u16)> {
+        let host = req.url.host_str();
+        let port = req.url.port_or_known_default();
+
+        match (host, port) {
+            (Some(host), Some(port)) => Ok((host.to_string(), port)),
+            _ => Err(Error::InvalidGatewayUrl(req.url.to_string()))
+        }
     }
+}
+
+/// Determines the action to take for the provided close code, returning an error for fatal close codes.
+fn close_action(shard_id: usize, code: Option<u16>) -> Result<ShardAction> {
+    let code = match code {
+        Some(c) => c,
+        None => {
+            warn!("[Shard {}] Gateway closed the connection without a close code.", shard_id);
+            return Ok(ShardAction::Autoreconnect);
+        }
+    };
 
-    fn get_addr_info(req: &Request) -> (String, u16) {
-        let host = req.url.host_str().expect("Could Not parse the Websocket Host.");
-        let port = req.url.port_or_known_default().expect("Could not parse the websocket port.");
+    match CloseCodes::from_code(code) {
+        Some(close) if close.is_fatal() => {
+            error!("[Shard {}] Gateway closed the connection with a fatal close code: {:?}", shard_id, close);
+            Err(Error::Closed(close))
+        },
+        Some(close) if close.invalidates_session() => {
+            warn!("[Shard {}] Gateway invalidated the session with close code {:?}, identifying again.", shard_id, close);
+            Ok(ShardAction::Reconnect)
+        },
+        close => {
+            warn!("[Shard {}] Gateway closed the connection with code {} ({:?}), attempting to resume.", shard_id, code, close);
+            Ok(ShardAction::Autoreconnect)
+        }
+    }
+}
 
-        (host.to_string(), port)
+fn encode_payload<T: SendablePacket>(encoding: Encoding, payload: T) -> Result<WebsocketMessage> {
+    match encoding {
+        Encoding::Json => Ok(WebsocketMessage::text(payload.to_json()?)),
+        Encoding::Etf => Ok(WebsocketMessage::binary(payload.to_etf()?))
     }
 }
 
@@ -363,4 +931,140 @@ fn send(sender: &Arc<Mutex<UnboundedSender<WebsocketMessage>>>, mess: WebsocketM
     sender.lock().start_send(mess)
         .map(|_| ())
         .map_err(From::from)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use futures::{
+        future,
+        Future,
+        stream::{Stream, Wait},
+        sync::{mpsc::{self, UnboundedReceiver}, oneshot}
+    };
+    use serde_json::Value;
+    use tokio_tungstenite::tungstenite::protocol::Message as WebsocketMessage;
+
+    use spectacles_model::gateway::{CloseCodes, ReceivePacket};
+
+    use crate::errors::Error;
+
+    use super::{close_action, Connection, Shard, ShardAction, ShardOptions, ShardStream};
+
+    const READY: &str = r#"{"op":0,"s":1,"t":"READY","d":{"v":6,"user":{"id":"1","username":"Mock","discriminator":"0001","avatar":null,"bot":true},"private_channels":[],"guilds":[],"session_id":"mock-session","_trace":["mock-gateway"],"shard":[0,1]}}"#;
+
+    /// Creates a shard without a websocket connection, along with the messages that it sends.
+    fn shard() -> (Shard, Wait<UnboundedReceiver<WebsocketMessage>>) {
+        let (sender, sent) = mpsc::unbounded();
+        let (control, control_rx) = mpsc::unbounded();
+        let (closed_tx, closed) = oneshot::channel();
+        let conn = Connection {
+            sender,
+            control,
+            closed,
+            stream: ShardStream {
+                inner: None,
+                control: control_rx,
+                _closed: closed_tx,
+            }
+        };
+
+        (Shard::from_connection(String::from("Bot mock"), [0, 1], String::new(), ShardOptions::default(), conn), sent.wait())
+    }
+
+    /// Replays packets received from the gateway, in order.
+    fn replay(shard: &mut Shard, packets: &[&str]) {
+        for packet in packets {
+            let packet: ReceivePacket = serde_json::from_str(packet).unwrap();
+            shard.fulfill_gateway(packet).unwrap();
+        }
+    }
+
+    fn next_payload(sent: &mut Wait<UnboundedReceiver<WebsocketMessage>>) -> Value {
+        match sent.next() {
+            Some(Ok(WebsocketMessage::Text(text))) => serde_json::from_str(&text).unwrap(),
+            other => panic!("Expected a text message, found {:?}", other)
+        }
+    }
+
+    fn dispatch(seq: u64) -> String {
+        format!(r#"{{"op":0,"s":{},"t":"MOCK_EVENT","d":{{}}}}"#, seq)
+    }
+
+    #[test]
+    fn heartbeat_sends_last_sequence() {
+        future::lazy(|| {
+            let (mut shard, mut sent) = shard();
+            shard.heartbeat().unwrap();
+            assert_eq!(next_payload(&mut sent), serde_json::json!({ "op": 1, "d": 0 }));
+
+            replay(&mut shard, &[READY, &dispatch(2), &dispatch(3)]);
+            shard.heartbeat().unwrap();
+            assert_eq!(next_payload(&mut sent), serde_json::json!({ "op": 1, "d": 3 }));
+
+            Ok::<(), ()>(())
+        }).wait().unwrap();
+    }
+
+    #[test]
+    fn resume_keeps_sequence_across_connections() {
+        future::lazy(|| {
+            let (mut shard, mut sent) = shard();
+            replay(&mut shard, &[READY, &dispatch(2), &dispatch(5)]);
+
+            // A new connection only resets the heartbeat, so the session is resumed from the last sequence.
+            shard.heartbeat.lock().reset();
+            shard.send_resume().unwrap();
+            let resume = next_payload(&mut sent);
+            assert_eq!(resume["op"], 6);
+            assert_eq!(resume["d"]["session_id"], "mock-session");
+            assert_eq!(resume["d"]["seq"], 5);
+
+            // Reconnecting without resuming starts a new session instead.
+            shard.reset_values().unwrap();
+            assert!(shard.session().is_none());
+            assert_eq!(shard.heartbeat.lock().seq, 0);
+
+            Ok::<(), ()>(())
+        }).wait().unwrap();
+    }
+
+    #[test]
+    fn invalid_session_clears_sequence() {
+        future::lazy(|| {
+            let (mut shard, _) = shard();
+            replay(&mut shard, &[READY, &dispatch(2)]);
+            let packet: ReceivePacket = serde_json::from_str(r#"{"op":9,"s":null,"t":null,"d":false}"#).unwrap();
+            match shard.fulfill_gateway(packet) {
+                Ok(ShardAction::Identify) => {},
+                _ => panic!("Expected an invalid session to identify again")
+            };
+            assert!(shard.session().is_none());
+            assert_eq!(shard.heartbeat.lock().seq, 0);
+
+            Ok::<(), ()>(())
+        }).wait().unwrap();
+    }
+
+    #[test]
+    fn fatal_close_codes_are_errors() {
+        match close_action(0, Some(4004)) {
+            Err(Error::Closed(CloseCodes::AuthenticationFailed)) => {},
+            Err(e) => panic!("Unexpected error for a fatal close code: {:?}", e),
+            Ok(_) => panic!("A fatal close code was treated as recoverable")
+        };
+        assert!(close_action(0, Some(4014)).is_err());
+    }
+
+    #[test]
+    fn recoverable_close_codes_reconnect() {
+        for code in &[Some(4000), None] {
+            match close_action(0, *code) {
+                Ok(ShardAction::Autoreconnect) => {},
+                _ => panic!("Expected close code {:?} to reconnect automatically", code)
+            };
+        }
+        match close_action(0, Some(4007)) {
+            Ok(ShardAction::Reconnect) => {},
+            _ => panic!("Expected an invalid sequence to reconnect with a new session")
+        };
+    }
+}
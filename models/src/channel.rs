@@ -195,4 +195,27 @@ pub enum ChannelType {
     Category,
     News,
     Store
-}
\ No newline at end of file
+}
+/// Represents a packet sent by the gateway when a message is pinned or unpinned in a channel.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChannelPinsUpdate {
+    /// The ID of the channel.
+    pub channel_id: Snowflake,
+    /// The time at which the most recent pinned message was pinned.
+    #[serde(default)]
+    pub last_pin_timestamp: Option<DateTime<FixedOffset>>
+}
+
+/// Represents a packet sent by the gateway when a user starts typing in a channel.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TypingStart {
+    /// The ID of the channel.
+    pub channel_id: Snowflake,
+    /// The ID of the guild, if the channel is in a guild.
+    #[serde(default)]
+    pub guild_id: Option<Snowflake>,
+    /// The ID of the user who started typing.
+    pub user_id: Snowflake,
+    /// The unix time, in seconds, at which the user started typing.
+    pub timestamp: u64
+}
@@ -0,0 +1,994 @@
+//! Serialization and deserialization of the Erlang External Term Format (ETF).
+//!
+//! The Discord gateway can optionally speak ETF instead of JSON. Terms are mapped onto the serde data model in the same way as JSON,
+//! so every type in this crate may be used with both encodings: binaries and atoms are strings, the `nil` atom is null,
+//! lists and tuples are sequences, and maps are maps or structs.
+use std::{
+    error::Error as StdError,
+    fmt::{Display, Formatter, Result as FmtResult},
+    result::Result as StdResult,
+};
+
+use serde::{
+    de::{self, Deserialize, DeserializeSeed, IntoDeserializer, Visitor},
+    ser::{self, Serialize},
+    forward_to_deserialize_any
+};
+use serde_json::{Error as JsonError, Value};
+
+const FORMAT_VERSION: u8 = 131;
+const NEW_FLOAT_EXT: u8 = 70;
+const SMALL_INTEGER_EXT: u8 = 97;
+const INTEGER_EXT: u8 = 98;
+const ATOM_EXT: u8 = 100;
+const SMALL_TUPLE_EXT: u8 = 104;
+const LARGE_TUPLE_EXT: u8 = 105;
+const NIL_EXT: u8 = 106;
+const STRING_EXT: u8 = 107;
+const LIST_EXT: u8 = 108;
+const BINARY_EXT: u8 = 109;
+const SMALL_BIG_EXT: u8 = 110;
+const LARGE_BIG_EXT: u8 = 111;
+const SMALL_ATOM_EXT: u8 = 115;
+const MAP_EXT: u8 = 116;
+const ATOM_UTF8_EXT: u8 = 118;
+const SMALL_ATOM_UTF8_EXT: u8 = 119;
+
+/// The name which `serde_json` uses to pass raw JSON values through other formats.
+const RAW_VALUE_TOKEN: &str = "$serde_json::private::RawValue";
+/// The deepest that lists, tuples and maps may be nested within a term.
+const RECURSION_LIMIT: usize = 128;
+
+/// A result type for ETF operations.
+pub type Result<T> = StdResult<T, Error>;
+
+/// An error which may occur while encoding or decoding ETF terms.
+#[derive(Debug)]
+pub enum Error {
+    /// A raw JSON value could not be converted to or from a term.
+    Json(JsonError),
+    /// The value could not be serialized or deserialized.
+    Message(String),
+    /// The term did not begin with the expected format version.
+    InvalidVersion(u8),
+    /// The term contained a tag which is not supported.
+    UnsupportedTag(u8),
+    /// The term ended before it could be fully decoded.
+    UnexpectedEof,
+    /// A string or atom in the term was not valid UTF-8.
+    InvalidUtf8,
+    /// An integer in the term was too large to be represented.
+    IntegerOverflow,
+    /// The term was nested too deeply.
+    RecursionLimitExceeded,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Error::InvalidVersion(v) => write!(f, "Invalid ETF format version: {}", v),
+            Error::UnsupportedTag(t) => write!(f, "Unsupported ETF tag: {}", t),
+            _ => f.write_str(self.description())
+        }
+    }
+}
+
+impl StdError for Error {
+    fn description(&self) -> &str {
+        match self {
+            Error::Json(e) => e.description(),
+            Error::Message(m) => m,
+            Error::InvalidVersion(_) => "The ETF term did not begin with a valid format version.",
+            Error::UnsupportedTag(_) => "The ETF term contained an unsupported tag.",
+            Error::UnexpectedEof => "The ETF term ended unexpectedly.",
+            Error::InvalidUtf8 => "The ETF term contained invalid UTF-8.",
+            Error::IntegerOverflow => "The ETF term contained an integer which was too large.",
+            Error::RecursionLimitExceeded => "The ETF term was nested too deeply."
+        }
+    }
+}
+
+impl From<JsonError> for Error {
+    fn from(err: JsonError) -> Self {
+        Error::Json(err)
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+/// Serializes the provided value into an ETF term.
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    let mut serializer = Serializer::new();
+    value.serialize(&mut serializer)?;
+
+    Ok(serializer.into_inner())
+}
+
+/// Deserializes an instance of type `T` from an ETF term.
+pub fn from_slice<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> Result<T> {
+    let mut deserializer = Deserializer::from_slice(bytes)?;
+
+    T::deserialize(&mut deserializer)
+}
+
+/// Decodes an ETF term into a JSON value.
+pub fn to_json_value(bytes: &[u8]) -> Result<Value> {
+    from_slice(bytes)
+}
+
+/// A serializer which encodes values as an ETF term.
+pub struct Serializer {
+    buf: Vec<u8>
+}
+
+impl Serializer {
+    /// Creates a serializer for a new term.
+    pub fn new() -> Self {
+        Serializer { buf: vec![FORMAT_VERSION] }
+    }
+
+    /// Consumes the serializer, returning the encoded term.
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    fn atom(&mut self, atom: &str) {
+        self.buf.push(SMALL_ATOM_UTF8_EXT);
+        self.buf.push(atom.len() as u8);
+        self.buf.extend_from_slice(atom.as_bytes());
+    }
+
+    fn binary(&mut self, bytes: &[u8]) {
+        self.buf.push(BINARY_EXT);
+        self.buf.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+        self.buf.extend_from_slice(bytes);
+    }
+
+    fn integer(&mut self, int: i64) {
+        if (0..=255).contains(&int) {
+            self.buf.push(SMALL_INTEGER_EXT);
+            self.buf.push(int as u8);
+        } else if int >= i64::from(i32::MIN) && int <= i64::from(i32::MAX) {
+            self.buf.push(INTEGER_EXT);
+            self.buf.extend_from_slice(&(int as i32).to_be_bytes());
+        } else {
+            self.big(int.wrapping_abs() as u64, int < 0);
+        }
+    }
+
+    fn big(&mut self, magnitude: u64, negative: bool) {
+        let mut bytes = magnitude.to_le_bytes().to_vec();
+        while bytes.last() == Some(&0) {
+            bytes.pop();
+        }
+        self.buf.push(SMALL_BIG_EXT);
+        self.buf.push(bytes.len() as u8);
+        self.buf.push(negative as u8);
+        self.buf.extend_from_slice(&bytes);
+    }
+
+    /// Begins a list or map, whose length is written once every element has been serialized.
+    fn begin(&mut self, tag: u8) -> Compound<'_> {
+        self.buf.push(tag);
+        let start = self.buf.len();
+        self.buf.extend_from_slice(&[0; 4]);
+
+        Compound { ser: self, tag, start, len: 0 }
+    }
+
+    /// Begins a map with a single key, the name of an enum variant.
+    fn variant(&mut self, variant: &str) {
+        self.buf.push(MAP_EXT);
+        self.buf.extend_from_slice(&1u32.to_be_bytes());
+        self.binary(variant.as_bytes());
+    }
+}
+
+impl Default for Serializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The state of a list or map which is being serialized.
+pub struct Compound<'a> {
+    ser: &'a mut Serializer,
+    tag: u8,
+    start: usize,
+    len: u32,
+}
+
+impl<'a> Compound<'a> {
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.len += 1;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn finish(self) -> Result<()> {
+        if self.tag == LIST_EXT && self.len == 0 {
+            // Empty lists are encoded as a lone tail.
+            self.ser.buf.truncate(self.start - 1);
+            self.ser.buf.push(NIL_EXT);
+            return Ok(());
+        };
+        self.ser.buf[self.start..self.start + 4].copy_from_slice(&self.len.to_be_bytes());
+        if self.tag == LIST_EXT {
+            self.ser.buf.push(NIL_EXT);
+        };
+
+        Ok(())
+    }
+}
+
+/// A struct which is being serialized, which may be a raw JSON value.
+pub enum StructCompound<'a> {
+    Map(Compound<'a>),
+    Raw(&'a mut Serializer)
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = StructCompound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.atom(if v { "true" } else { "false" });
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.integer(v);
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.serialize_i64(i64::from(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        if v <= i64::MAX as u64 {
+            self.integer(v as i64);
+        } else {
+            self.big(v, false);
+        };
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.serialize_f64(f64::from(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.buf.push(NEW_FLOAT_EXT);
+        self.buf.extend_from_slice(&v.to_bits().to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.binary(v.encode_utf8(&mut [0; 4]).as_bytes());
+        Ok(())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.binary(v.as_bytes());
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.binary(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        self.atom("nil");
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<()> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _index: u32, variant: &'static str, value: &T) -> Result<()> {
+        self.variant(variant);
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'a>> {
+        Ok(self.begin(LIST_EXT))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Compound<'a>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Compound<'a>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, variant: &'static str, len: usize) -> Result<Compound<'a>> {
+        self.variant(variant);
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'a>> {
+        Ok(self.begin(MAP_EXT))
+    }
+
+    fn serialize_struct(self, name: &'static str, _len: usize) -> Result<StructCompound<'a>> {
+        if name == RAW_VALUE_TOKEN {
+            Ok(StructCompound::Raw(self))
+        } else {
+            Ok(StructCompound::Map(self.begin(MAP_EXT)))
+        }
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, variant: &'static str, _len: usize) -> Result<Compound<'a>> {
+        self.variant(variant);
+        Ok(self.begin(MAP_EXT))
+    }
+}
+
+impl<'a> ser::SerializeSeq for Compound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTuple for Compound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for Compound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for Compound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeMap for Compound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        self.element(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeStruct for StructCompound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        match self {
+            StructCompound::Map(map) => {
+                map.element(key)?;
+                value.serialize(&mut *map.ser)
+            },
+            // Raw JSON values are serialized as a struct with a single field, which holds the JSON text.
+            StructCompound::Raw(ser) => match serde_json::to_value(value)? {
+                Value::String(json) => serde_json::from_str::<Value>(&json)?.serialize(&mut **ser),
+                _ => Err(ser::Error::custom("expected raw JSON text"))
+            }
+        }
+    }
+
+    fn end(self) -> Result<()> {
+        match self {
+            StructCompound::Map(map) => map.finish(),
+            StructCompound::Raw(_) => Ok(())
+        }
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for Compound<'a> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.element(key)?;
+        value.serialize(&mut *self.ser)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+/// A deserializer which decodes values from an ETF term.
+pub struct Deserializer<'de> {
+    bytes: &'de [u8],
+    pos: usize,
+    remaining_depth: usize,
+}
+
+impl<'de> Deserializer<'de> {
+    /// Creates a deserializer for the provided term, checking its format version.
+    pub fn from_slice(bytes: &'de [u8]) -> Result<Self> {
+        let mut deserializer = Deserializer {
+            bytes,
+            pos: 0,
+            remaining_depth: RECURSION_LIMIT
+        };
+        let version = deserializer.read_u8()?;
+        if version != FORMAT_VERSION {
+            return Err(Error::InvalidVersion(version));
+        };
+
+        Ok(deserializer)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'de [u8]> {
+        if len > self.bytes.len() - self.pos {
+            return Err(Error::UnexpectedEof);
+        };
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+
+        Ok(slice)
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        let b = self.read_bytes(2)?;
+        Ok(u16::from(b[0]) << 8 | u16::from(b[1]))
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        let b = self.read_bytes(4)?;
+        Ok(u32::from(b[0]) << 24 | u32::from(b[1]) << 16 | u32::from(b[2]) << 8 | u32::from(b[3]))
+    }
+
+    fn read_str(&mut self, len: usize) -> Result<&'de str> {
+        let bytes = self.read_bytes(len)?;
+        std::str::from_utf8(bytes).map_err(|_| Error::InvalidUtf8)
+    }
+
+    fn peek(&self) -> Result<u8> {
+        self.bytes.get(self.pos).cloned().ok_or(Error::UnexpectedEof)
+    }
+
+    /// Consumes the next term if it is the `nil` atom, which represents a missing value.
+    fn parse_nil(&mut self) -> Result<bool> {
+        let start = self.pos;
+        let len = match self.read_u8()? {
+            ATOM_EXT | ATOM_UTF8_EXT => self.read_u16()? as usize,
+            SMALL_ATOM_EXT | SMALL_ATOM_UTF8_EXT => self.read_u8()? as usize,
+            _ => {
+                self.pos = start;
+                return Ok(false);
+            }
+        };
+        let atom = self.read_bytes(len)?;
+        if atom == b"nil" || atom == b"null" {
+            return Ok(true);
+        };
+        self.pos = start;
+
+        Ok(false)
+    }
+
+    /// Checks that the input could hold the provided amount of terms, as each term takes up at least one byte.
+    /// This rejects malformed lengths before anything is decoded.
+    fn check_len(&self, terms: usize) -> Result<()> {
+        if terms > self.bytes.len() - self.pos {
+            return Err(Error::UnexpectedEof);
+        };
+
+        Ok(())
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        if self.remaining_depth == 0 {
+            return Err(Error::RecursionLimitExceeded);
+        };
+        self.remaining_depth -= 1;
+
+        Ok(())
+    }
+
+    fn visit_atom<V: Visitor<'de>>(&mut self, len: usize, visitor: V) -> Result<V::Value> {
+        match self.read_str(len)? {
+            "nil" | "null" => visitor.visit_unit(),
+            "true" => visitor.visit_bool(true),
+            "false" => visitor.visit_bool(false),
+            atom => visitor.visit_borrowed_str(atom)
+        }
+    }
+
+    fn visit_big<V: Visitor<'de>>(&mut self, len: usize, visitor: V) -> Result<V::Value> {
+        let sign = self.read_u8()?;
+        let digits = self.read_bytes(len)?;
+        if len > 8 {
+            return Err(Error::IntegerOverflow);
+        };
+        let magnitude = digits.iter().rev().fold(0u64, |acc, b| acc << 8 | u64::from(*b));
+
+        if sign == 0 {
+            visitor.visit_u64(magnitude)
+        } else if magnitude <= 1 << 63 {
+            visitor.visit_i64((magnitude as i64).wrapping_neg())
+        } else {
+            Err(Error::IntegerOverflow)
+        }
+    }
+
+    fn visit_seq<V: Visitor<'de>>(&mut self, len: usize, list: bool, visitor: V) -> Result<V::Value> {
+        self.check_len(len)?;
+        self.enter()?;
+        let mut access = Access { de: &mut *self, remaining: len };
+        let value = visitor.visit_seq(&mut access);
+        let remaining = access.remaining;
+        self.remaining_depth += 1;
+        let value = value?;
+        if remaining > 0 {
+            return Err(de::Error::invalid_length(len, &"fewer elements in the list"));
+        };
+        if list {
+            // Proper lists end with an empty list as their tail.
+            de::IgnoredAny::deserialize(&mut *self)?;
+        };
+
+        Ok(value)
+    }
+
+    fn visit_map<V: Visitor<'de>>(&mut self, arity: usize, visitor: V) -> Result<V::Value> {
+        self.check_len(arity.saturating_mul(2))?;
+        self.enter()?;
+        let mut access = Access { de: &mut *self, remaining: arity };
+        let value = visitor.visit_map(&mut access);
+        let remaining = access.remaining;
+        self.remaining_depth += 1;
+        let value = value?;
+        if remaining > 0 {
+            return Err(de::Error::invalid_length(arity, &"fewer entries in the map"));
+        };
+
+        Ok(value)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.read_u8()? {
+            SMALL_INTEGER_EXT => visitor.visit_u8(self.read_u8()?),
+            INTEGER_EXT => visitor.visit_i32(self.read_u32()? as i32),
+            NEW_FLOAT_EXT => {
+                let mut bits = [0; 8];
+                bits.copy_from_slice(self.read_bytes(8)?);
+                visitor.visit_f64(f64::from_bits(u64::from_be_bytes(bits)))
+            },
+            ATOM_EXT | ATOM_UTF8_EXT => {
+                let len = self.read_u16()? as usize;
+                self.visit_atom(len, visitor)
+            },
+            SMALL_ATOM_EXT | SMALL_ATOM_UTF8_EXT => {
+                let len = self.read_u8()? as usize;
+                self.visit_atom(len, visitor)
+            },
+            SMALL_TUPLE_EXT => {
+                let arity = self.read_u8()? as usize;
+                self.visit_seq(arity, false, visitor)
+            },
+            LARGE_TUPLE_EXT => {
+                let arity = self.read_u32()? as usize;
+                self.visit_seq(arity, false, visitor)
+            },
+            NIL_EXT => self.visit_seq(0, false, visitor),
+            STRING_EXT => {
+                let len = self.read_u16()? as usize;
+                visitor.visit_borrowed_str(self.read_str(len)?)
+            },
+            LIST_EXT => {
+                let len = self.read_u32()? as usize;
+                self.visit_seq(len, true, visitor)
+            },
+            BINARY_EXT => {
+                let len = self.read_u32()? as usize;
+                visitor.visit_borrowed_str(self.read_str(len)?)
+            },
+            SMALL_BIG_EXT => {
+                let len = self.read_u8()? as usize;
+                self.visit_big(len, visitor)
+            },
+            LARGE_BIG_EXT => {
+                let len = self.read_u32()? as usize;
+                self.visit_big(len, visitor)
+            },
+            MAP_EXT => {
+                let arity = self.read_u32()? as usize;
+                self.visit_map(arity, visitor)
+            },
+            other => Err(Error::UnsupportedTag(other))
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.peek()? == BINARY_EXT {
+            self.read_u8()?;
+            let len = self.read_u32()? as usize;
+            visitor.visit_borrowed_bytes(self.read_bytes(len)?)
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        if self.parse_nil()? {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, name: &'static str, visitor: V) -> Result<V::Value> {
+        if name == RAW_VALUE_TOKEN {
+            // Raw JSON values are deserialized from a map with a single entry, which holds the JSON text of the term.
+            let value = Value::deserialize(&mut *self)?;
+            visitor.visit_map(RawValueAccess { json: Some(value.to_string()) })
+        } else {
+            visitor.visit_newtype_struct(self)
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        if self.peek()? != MAP_EXT {
+            return visitor.visit_enum(Enum { de: self, unit: true });
+        };
+        self.read_u8()?;
+        let arity = self.read_u32()? as usize;
+        if arity != 1 {
+            return Err(de::Error::invalid_length(arity, &"a map with a single key"));
+        };
+        self.enter()?;
+        let value = visitor.visit_enum(Enum { de: &mut *self, unit: false });
+        self.remaining_depth += 1;
+
+        value
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string
+        unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// Provides the elements of a list or tuple, or the entries of a map.
+struct Access<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    remaining: usize,
+}
+
+impl<'a, 'de> de::SeqAccess<'de> for Access<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        };
+        self.remaining -= 1;
+
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'a, 'de> de::MapAccess<'de> for Access<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        };
+        self.remaining -= 1;
+
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+/// Provides an enum variant, either as a string for unit variants, or as a map with a single key.
+struct Enum<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    unit: bool,
+}
+
+impl<'a, 'de> de::EnumAccess<'de> for Enum<'a, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self)> {
+        let variant = seed.deserialize(&mut *self.de)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'a, 'de> de::VariantAccess<'de> for Enum<'a, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        if self.unit {
+            Ok(())
+        } else {
+            de::Deserialize::deserialize(self.de)
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        if self.unit {
+            return Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"newtype variant"));
+        };
+        seed.deserialize(self.de)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        if self.unit {
+            return Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"tuple variant"));
+        };
+        de::Deserializer::deserialize_seq(self.de, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        if self.unit {
+            return Err(de::Error::invalid_type(de::Unexpected::UnitVariant, &"struct variant"));
+        };
+        de::Deserializer::deserialize_map(self.de, visitor)
+    }
+}
+
+/// Provides the JSON text of a term to `serde_json`'s raw values.
+struct RawValueAccess {
+    json: Option<String>
+}
+
+impl<'de> de::MapAccess<'de> for RawValueAccess {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        if self.json.is_none() {
+            return Ok(None);
+        };
+        let key: de::value::StrDeserializer<Error> = RAW_VALUE_TOKEN.into_deserializer();
+
+        seed.deserialize(key).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let json: de::value::StringDeserializer<Error> = self.json.take().unwrap_or_default().into_deserializer();
+
+        seed.deserialize(json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::gateway::{GatewayEvent, Opcodes, ReadyPacket, ReceivePacket};
+    use crate::message::Message;
+
+    use super::*;
+
+    const READY: &[u8] = include_bytes!("../tests/fixtures/ready.etf");
+    const MESSAGE_CREATE: &[u8] = include_bytes!("../tests/fixtures/message_create.etf");
+
+    /// Decodes a term, then checks that encoding and decoding it again yields the same value.
+    fn round_trip(bytes: &[u8]) -> Value {
+        let value = to_json_value(bytes).expect("Failed to decode the term");
+        let encoded = to_vec(&value).expect("Failed to encode the term");
+        assert_eq!(to_json_value(&encoded).expect("Failed to decode the encoded term"), value);
+
+        value
+    }
+
+    #[test]
+    fn ready_round_trip() {
+        let value = round_trip(READY);
+        assert_eq!(value["t"], "READY");
+        assert_eq!(value["d"]["user"]["id"], 218844420613734401u64);
+        assert_eq!(value["d"]["user"]["email"], Value::Null);
+
+        let packet: ReceivePacket = from_slice(READY).unwrap();
+        match packet.op {
+            Opcodes::Dispatch => {},
+            op => panic!("Expected a dispatch packet, found {:?}", op)
+        };
+        assert_eq!(packet.s, Some(1));
+        assert_eq!(packet.t, Some(GatewayEvent::READY));
+        let ready: ReadyPacket = serde_json::from_str(packet.d.get()).unwrap();
+        assert_eq!(ready.user.id.0, 218844420613734401);
+        assert!(ready.user.bot);
+        assert_eq!(ready.session_id, "d8b1f9c4e3a2b7f06c5d4e3f2a1b0c9d");
+        assert_eq!(ready.guilds.len(), 2);
+        assert!(ready.guilds[1].unavailable);
+        assert_eq!(ready.shard, [0, 1]);
+    }
+
+    #[test]
+    fn message_create_round_trip() {
+        let value = round_trip(MESSAGE_CREATE);
+        assert_eq!(value["d"]["content"], "Hello, étf! 👋");
+
+        let packet: ReceivePacket = from_slice(MESSAGE_CREATE).unwrap();
+        assert_eq!(packet.s, Some(42));
+        assert_eq!(packet.t, Some(GatewayEvent::MESSAGE_CREATE));
+        let message: Message = serde_json::from_str(packet.d.get()).unwrap();
+        assert_eq!(message.id.0, 562447300907220992);
+        assert_eq!(message.guild_id.map(|id| id.0), Some(81384788765712384));
+        assert_eq!(message.author.username, "Texlo");
+        assert_eq!(message.author.avatar, None);
+        assert_eq!(message.nonce.map(|n| n.0), Some(562447297815986176));
+        assert_eq!(message.mention_roles[0].0, 265156286406983680);
+        assert!(message.edited_timestamp.is_none());
+    }
+
+    #[test]
+    fn typed_round_trip() {
+        let ready: ReadyPacket = serde_json::from_str(from_slice::<ReceivePacket>(READY).unwrap().d.get()).unwrap();
+        let encoded = to_vec(&ready).unwrap();
+        let decoded: ReadyPacket = from_slice(&encoded).unwrap();
+        assert_eq!(decoded.session_id, ready.session_id);
+        assert_eq!(decoded.user.id.0, ready.user.id.0);
+        assert_eq!(decoded.guilds.len(), ready.guilds.len());
+    }
+
+    #[test]
+    fn encodes_terms() {
+        let encoded = to_vec(&json!({ "a": [], "b": [1, -1, 300, null, true], "c": 1u64 << 40 })).unwrap();
+        assert_eq!(encoded, vec![
+            131, MAP_EXT, 0, 0, 0, 3,
+            BINARY_EXT, 0, 0, 0, 1, b'a', NIL_EXT,
+            BINARY_EXT, 0, 0, 0, 1, b'b', LIST_EXT, 0, 0, 0, 5,
+            SMALL_INTEGER_EXT, 1,
+            INTEGER_EXT, 255, 255, 255, 255,
+            INTEGER_EXT, 0, 0, 1, 44,
+            SMALL_ATOM_UTF8_EXT, 3, b'n', b'i', b'l',
+            SMALL_ATOM_UTF8_EXT, 4, b't', b'r', b'u', b'e',
+            NIL_EXT,
+            BINARY_EXT, 0, 0, 0, 1, b'c', SMALL_BIG_EXT, 6, 0, 0, 0, 0, 0, 0, 1
+        ]);
+    }
+
+    #[test]
+    fn rejects_oversized_lengths() {
+        // A list, map and tuple which claim far more elements than the input holds.
+        for term in &[
+            vec![131, LIST_EXT, 255, 255, 255, 255, NIL_EXT],
+            vec![131, MAP_EXT, 255, 255, 255, 255],
+            vec![131, LARGE_TUPLE_EXT, 255, 255, 255, 255],
+            vec![131, BINARY_EXT, 255, 255, 255, 255, b'a'],
+        ] {
+            match to_json_value(term) {
+                Err(Error::UnexpectedEof) => {},
+                other => panic!("Expected an unexpected EOF error, got {:?}", other)
+            };
+        }
+    }
+
+    #[test]
+    fn rejects_deep_nesting() {
+        let mut term = vec![131];
+        for _ in 0..RECURSION_LIMIT + 1 {
+            term.extend_from_slice(&[SMALL_TUPLE_EXT, 1]);
+        }
+        term.push(NIL_EXT);
+
+        match to_json_value(&term) {
+            Err(Error::RecursionLimitExceeded) => {},
+            other => panic!("Expected the recursion limit to be exceeded, got {:?}", other)
+        };
+    }
+}
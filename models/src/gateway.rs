@@ -1,6 +1,10 @@
 //! Structs representing the various elements of the Discord gateway.
 use std::fmt::{Display, Formatter, Result as FmtResult};
 
+use serde::{
+    de::{Deserialize, Deserializer},
+    ser::{Serialize, Serializer},
+};
 use serde_json::{
     Error as JsonError,
     value::RawValue,
@@ -8,10 +12,36 @@ use serde_json::{
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
 use crate::{
-    guild::UnavailableGuild,
-    presence::{ClientActivity, ClientPresence},
+    channel::{Channel, ChannelPinsUpdate, TypingStart},
+    etf::{self, Error as EtfError},
+    guild::{
+        Guild,
+        GuildBanAdd,
+        GuildBanRemove,
+        GuildEmojisUpdate,
+        GuildIntegrationsUpdate,
+        GuildMember,
+        GuildMemberRemove,
+        GuildMembersChunk,
+        GuildMemberUpdate,
+        GuildRoleCreate,
+        GuildRoleDelete,
+        GuildRoleUpdate,
+        UnavailableGuild
+    },
+    message::{
+        Message,
+        MessageDelete,
+        MessageDeleteBulk,
+        MessageReactionAdd,
+        MessageReactionRemove,
+        MessageReactionRemoveAll,
+        WebhooksUpdate
+    },
+    presence::{ClientActivity, ClientPresence, Presence},
     Snowflake,
-    User
+    User,
+    voice::{VoiceServerUpdate, VoiceState}
 };
 use crate::presence::Status;
 
@@ -19,10 +49,18 @@ use crate::presence::Status;
 pub trait SendablePacket {
     fn to_json(self) -> Result<String, JsonError>;
     fn bytes(self) -> Result<Vec<u8>, JsonError>;
+
+    /// Encodes the packet as an ETF term, for gateways using the `etf` encoding.
+    fn to_etf(self) -> Result<Vec<u8>, EtfError> where Self: Sized {
+        let json = self.to_json()?;
+        let value: serde_json::Value = serde_json::from_str(&json)?;
+
+        etf::to_vec(&value)
+    }
 }
 
 /// Returns useful information about the application from the gateway.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GatewayBot {
     /// The websocket URL that can be used to begin connecting to this gateway.
     pub url: String,
@@ -32,14 +70,21 @@ pub struct GatewayBot {
     pub session_start_limit: SessionStartLimit
 }
 /// Useful information about a bot's session start limit.
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SessionStartLimit {
     /// The total number of session starts the current user is allowed.
     pub total: i32,
     /// The remaining number of session starts the current user is allowed.
     pub remaining: i32,
-    /// The time until the limit resets.
+    /// The time until the limit resets, in milliseconds.
     pub reset_after: i32,
+    /// The number of identify requests allowed per 5 seconds.
+    #[serde(default = "default_max_concurrency")]
+    pub max_concurrency: usize,
+}
+
+fn default_max_concurrency() -> usize {
+    1
 }
 
 /// A JSON packet that the client would receive over the Discord gateway.
@@ -107,7 +152,127 @@ pub struct IdentifyPacket {
     /// Holds the sharding information for this shard.
     pub shard: [usize; 2],
     /// The initial presence of this shard.
-    pub presence: Option<ClientPresence>
+    pub presence: Option<ClientPresence>,
+    /// The gateway intents that this shard will receive events for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intents: Option<Intents>
+}
+
+bitflags! {
+    /// A set of gateway intents, which determine the events that a shard will receive.
+    pub struct Intents: u64 {
+        /// Guild, channel and role events.
+        const GUILDS = 1 << 0;
+        /// Guild member events.
+        const GUILD_MEMBERS = 1 << 1;
+        /// Guild ban events.
+        const GUILD_BANS = 1 << 2;
+        /// Guild emoji events.
+        const GUILD_EMOJIS = 1 << 3;
+        /// Guild integration events.
+        const GUILD_INTEGRATIONS = 1 << 4;
+        /// Guild webhook events.
+        const GUILD_WEBHOOKS = 1 << 5;
+        /// Guild invite events.
+        const GUILD_INVITES = 1 << 6;
+        /// Voice state events.
+        const GUILD_VOICE_STATES = 1 << 7;
+        /// Presence events.
+        const GUILD_PRESENCES = 1 << 8;
+        /// Message events in guilds.
+        const GUILD_MESSAGES = 1 << 9;
+        /// Message reaction events in guilds.
+        const GUILD_MESSAGE_REACTIONS = 1 << 10;
+        /// Typing events in guilds.
+        const GUILD_MESSAGE_TYPING = 1 << 11;
+        /// Message events in direct messages.
+        const DIRECT_MESSAGES = 1 << 12;
+        /// Message reaction events in direct messages.
+        const DIRECT_MESSAGE_REACTIONS = 1 << 13;
+        /// Typing events in direct messages.
+        const DIRECT_MESSAGE_TYPING = 1 << 14;
+    }
+}
+
+impl Intents {
+    /// The gateway events which are enabled by this set of intents.
+    pub fn events(&self) -> Vec<GatewayEvent> {
+        use self::GatewayEvent::*;
+        let mut events = Vec::new();
+
+        if self.contains(Intents::GUILDS) {
+            events.extend_from_slice(&[
+                GUILD_CREATE, GUILD_UPDATE, GUILD_DELETE,
+                GUILD_ROLE_CREATE, GUILD_ROLE_UPDATE, GUILD_ROLE_DELETE,
+                CHANNEL_CREATE, CHANNEL_UPDATE, CHANNEL_DELETE, CHANNEL_PINS_UPDATE
+            ]);
+        };
+        if self.contains(Intents::GUILD_MEMBERS) {
+            events.extend_from_slice(&[GUILD_MEMBER_ADD, GUILD_MEMBER_UPDATE, GUILD_MEMBER_REMOVE]);
+        };
+        if self.contains(Intents::GUILD_BANS) {
+            events.extend_from_slice(&[GUILD_BAN_ADD, GUILD_BAN_REMOVE]);
+        };
+        if self.contains(Intents::GUILD_EMOJIS) {
+            events.push(GUILD_EMOJIS_UPDATE);
+        };
+        if self.contains(Intents::GUILD_INTEGRATIONS) {
+            events.push(GUILD_INTEGRATIONS_UPDATE);
+        };
+        if self.contains(Intents::GUILD_WEBHOOKS) {
+            events.push(WEBHOOKS_UPDATE);
+        };
+        if self.contains(Intents::GUILD_VOICE_STATES) {
+            events.push(VOICE_STATE_UPDATE);
+        };
+        if self.contains(Intents::GUILD_PRESENCES) {
+            events.push(PRESENCE_UPDATE);
+        };
+        if self.intersects(Intents::GUILD_MESSAGES | Intents::DIRECT_MESSAGES) {
+            events.extend_from_slice(&[MESSAGE_CREATE, MESSAGE_UPDATE, MESSAGE_DELETE]);
+        };
+        if self.contains(Intents::GUILD_MESSAGES) {
+            events.push(MESSAGE_DELETE_BULK);
+        };
+        if self.contains(Intents::DIRECT_MESSAGES) && !self.contains(Intents::GUILDS) {
+            events.push(CHANNEL_PINS_UPDATE);
+        };
+        if self.intersects(Intents::GUILD_MESSAGE_REACTIONS | Intents::DIRECT_MESSAGE_REACTIONS) {
+            events.extend_from_slice(&[MESSAGE_REACTION_ADD, MESSAGE_REACTION_REMOVE, MESSAGE_REACTION_REMOVE_ALL]);
+        };
+        if self.intersects(Intents::GUILD_MESSAGE_TYPING | Intents::DIRECT_MESSAGE_TYPING) {
+            events.push(TYPING_START);
+        };
+
+        events
+    }
+
+    /// Whether or not this set of intents enables the provided gateway event.
+    /// Events which are not tied to an intent, such as `READY`, are always enabled.
+    pub fn enables(&self, event: &GatewayEvent) -> bool {
+        if Intents::all().events().contains(event) {
+            self.events().contains(event)
+        } else {
+            true
+        }
+    }
+}
+
+impl Serialize for Intents {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.serialize_u64(self.bits())
+    }
+}
+
+impl<'de> Deserialize<'de> for Intents {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de>
+    {
+        let bits = u64::deserialize(deserializer)?;
+        Ok(Intents::from_bits_truncate(bits))
+    }
 }
 
 
@@ -144,7 +309,9 @@ pub struct ResumeSessionPacket {
     pub token: String
 }
 /// A JSON packet used to send a heartbeat to the gateway.
+/// The packet's data is the sequence number itself, rather than an object containing it.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(transparent)]
 pub struct HeartbeatPacket {
     /// The shard's last sequence number.
     pub seq: u64
@@ -158,7 +325,10 @@ pub struct RequestGuildMembers {
     /// A string that the username starts with. If omitted, returns all members.
     pub query: String,
     /// The maximum number of members to send. If omitted, requests all members.
-    pub limit: i32
+    pub limit: i32,
+    /// A nonce which identifies the guild members chunks sent in response to this request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>
 }
 
 
@@ -331,10 +501,39 @@ pub struct ResumedPacket {
     pub _trace: Vec<String>
 
 }
-#[derive(Debug, Deserialize, Serialize, Clone)]
-#[allow(non_camel_case_types)]
-/// An organized list of Discord gateway events.
-pub enum GatewayEvent {
+macro_rules! gateway_events {
+    ($($name:ident),*) => {
+        /// An organized list of Discord gateway events.
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        #[allow(non_camel_case_types)]
+        pub enum GatewayEvent {
+            $($name,)*
+            /// An event which is not recognised by this library, along with its name.
+            Unknown(String)
+        }
+
+        impl GatewayEvent {
+            /// The name of this event, as sent by the gateway.
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $(GatewayEvent::$name => stringify!($name),)*
+                    GatewayEvent::Unknown(name) => name
+                }
+            }
+        }
+
+        impl<'a> From<&'a str> for GatewayEvent {
+            fn from(name: &'a str) -> Self {
+                match name {
+                    $(stringify!($name) => GatewayEvent::$name,)*
+                    other => GatewayEvent::Unknown(other.to_string())
+                }
+            }
+        }
+    }
+}
+
+gateway_events!(
     HELLO,
     READY,
     RESUMED,
@@ -371,11 +570,138 @@ pub enum GatewayEvent {
     VOICE_STATE_UPDATE,
     VOICE_SERVER_UPDATE,
     WEBHOOKS_UPDATE
-}
+);
 
 impl Display for GatewayEvent {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        write!(f, "{:?}", self)
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for GatewayEvent {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for GatewayEvent {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de>
+    {
+        let name = String::deserialize(deserializer)?;
+        Ok(GatewayEvent::from(name.as_str()))
+    }
+}
+
+/// A gateway dispatch, decoded into the model struct of its event.
+#[derive(Debug, Clone)]
+pub enum DispatchEvent {
+    Ready(ReadyPacket),
+    Resumed(ResumedPacket),
+    ChannelCreate(Channel),
+    ChannelUpdate(Channel),
+    ChannelDelete(Channel),
+    ChannelPinsUpdate(ChannelPinsUpdate),
+    GuildCreate(Guild),
+    GuildUpdate(Guild),
+    GuildDelete(UnavailableGuild),
+    GuildBanAdd(GuildBanAdd),
+    GuildBanRemove(GuildBanRemove),
+    GuildEmojisUpdate(GuildEmojisUpdate),
+    GuildIntegrationsUpdate(GuildIntegrationsUpdate),
+    GuildMemberAdd(GuildMember),
+    GuildMemberRemove(GuildMemberRemove),
+    GuildMemberUpdate(GuildMemberUpdate),
+    GuildMembersChunk(GuildMembersChunk),
+    GuildRoleCreate(GuildRoleCreate),
+    GuildRoleUpdate(GuildRoleUpdate),
+    GuildRoleDelete(GuildRoleDelete),
+    MessageCreate(Message),
+    MessageUpdate(Message),
+    MessageDelete(MessageDelete),
+    MessageDeleteBulk(MessageDeleteBulk),
+    MessageReactionAdd(MessageReactionAdd),
+    MessageReactionRemove(MessageReactionRemove),
+    MessageReactionRemoveAll(MessageReactionRemoveAll),
+    PresenceUpdate(Presence),
+    PresencesReplace(Vec<Presence>),
+    TypingStart(TypingStart),
+    UserUpdate(User),
+    VoiceStateUpdate(VoiceState),
+    VoiceServerUpdate(VoiceServerUpdate),
+    WebhooksUpdate(WebhooksUpdate),
+    /// An event which could not be decoded into a model struct, along with its raw data.
+    Raw {
+        /// The name of the event, if it was provided.
+        event: Option<GatewayEvent>,
+        /// The raw JSON data of the event.
+        data: Box<RawValue>
+    }
+}
+
+impl DispatchEvent {
+    /// Decodes the data of a dispatch packet into the model struct of its event.
+    /// Events which fail to decode are logged at the debug level, and returned as the `Raw` variant.
+    pub fn from_packet(packet: &ReceivePacket) -> Self {
+        let event = match packet.t {
+            Some(ref t) => t,
+            None => return DispatchEvent::raw(packet)
+        };
+        let data = packet.d.get();
+
+        let decoded = match event {
+            GatewayEvent::READY => serde_json::from_str(data).map(DispatchEvent::Ready),
+            GatewayEvent::RESUMED => serde_json::from_str(data).map(DispatchEvent::Resumed),
+            GatewayEvent::CHANNEL_CREATE => serde_json::from_str(data).map(DispatchEvent::ChannelCreate),
+            GatewayEvent::CHANNEL_UPDATE => serde_json::from_str(data).map(DispatchEvent::ChannelUpdate),
+            GatewayEvent::CHANNEL_DELETE => serde_json::from_str(data).map(DispatchEvent::ChannelDelete),
+            GatewayEvent::CHANNEL_PINS_UPDATE => serde_json::from_str(data).map(DispatchEvent::ChannelPinsUpdate),
+            GatewayEvent::GUILD_CREATE => serde_json::from_str(data).map(DispatchEvent::GuildCreate),
+            GatewayEvent::GUILD_UPDATE => serde_json::from_str(data).map(DispatchEvent::GuildUpdate),
+            GatewayEvent::GUILD_DELETE => serde_json::from_str(data).map(DispatchEvent::GuildDelete),
+            GatewayEvent::GUILD_BAN_ADD => serde_json::from_str(data).map(DispatchEvent::GuildBanAdd),
+            GatewayEvent::GUILD_BAN_REMOVE => serde_json::from_str(data).map(DispatchEvent::GuildBanRemove),
+            GatewayEvent::GUILD_EMOJIS_UPDATE => serde_json::from_str(data).map(DispatchEvent::GuildEmojisUpdate),
+            GatewayEvent::GUILD_INTEGRATIONS_UPDATE => serde_json::from_str(data).map(DispatchEvent::GuildIntegrationsUpdate),
+            GatewayEvent::GUILD_MEMBER_ADD => serde_json::from_str(data).map(DispatchEvent::GuildMemberAdd),
+            GatewayEvent::GUILD_MEMBER_REMOVE => serde_json::from_str(data).map(DispatchEvent::GuildMemberRemove),
+            GatewayEvent::GUILD_MEMBER_UPDATE => serde_json::from_str(data).map(DispatchEvent::GuildMemberUpdate),
+            GatewayEvent::GUILD_MEMBERS_CHUNK => serde_json::from_str(data).map(DispatchEvent::GuildMembersChunk),
+            GatewayEvent::GUILD_ROLE_CREATE => serde_json::from_str(data).map(DispatchEvent::GuildRoleCreate),
+            GatewayEvent::GUILD_ROLE_UPDATE => serde_json::from_str(data).map(DispatchEvent::GuildRoleUpdate),
+            GatewayEvent::GUILD_ROLE_DELETE => serde_json::from_str(data).map(DispatchEvent::GuildRoleDelete),
+            GatewayEvent::MESSAGE_CREATE => serde_json::from_str(data).map(DispatchEvent::MessageCreate),
+            GatewayEvent::MESSAGE_UPDATE => serde_json::from_str(data).map(DispatchEvent::MessageUpdate),
+            GatewayEvent::MESSAGE_DELETE => serde_json::from_str(data).map(DispatchEvent::MessageDelete),
+            GatewayEvent::MESSAGE_DELETE_BULK => serde_json::from_str(data).map(DispatchEvent::MessageDeleteBulk),
+            GatewayEvent::MESSAGE_REACTION_ADD => serde_json::from_str(data).map(DispatchEvent::MessageReactionAdd),
+            GatewayEvent::MESSAGE_REACTION_REMOVE => serde_json::from_str(data).map(DispatchEvent::MessageReactionRemove),
+            GatewayEvent::MESSAGE_REACTION_REMOVE_ALL => serde_json::from_str(data).map(DispatchEvent::MessageReactionRemoveAll),
+            GatewayEvent::PRESENCE_UPDATE => serde_json::from_str(data).map(DispatchEvent::PresenceUpdate),
+            GatewayEvent::PRESENCES_REPLACE => serde_json::from_str(data).map(DispatchEvent::PresencesReplace),
+            GatewayEvent::TYPING_START => serde_json::from_str(data).map(DispatchEvent::TypingStart),
+            GatewayEvent::USER_UPDATE => serde_json::from_str(data).map(DispatchEvent::UserUpdate),
+            GatewayEvent::VOICE_STATE_UPDATE => serde_json::from_str(data).map(DispatchEvent::VoiceStateUpdate),
+            GatewayEvent::VOICE_SERVER_UPDATE => serde_json::from_str(data).map(DispatchEvent::VoiceServerUpdate),
+            GatewayEvent::WEBHOOKS_UPDATE => serde_json::from_str(data).map(DispatchEvent::WebhooksUpdate),
+            // These events are never dispatched, and are handled by the shard itself.
+            GatewayEvent::HELLO | GatewayEvent::INVALID_SESSION => return DispatchEvent::raw(packet),
+            GatewayEvent::Unknown(_) => return DispatchEvent::raw(packet)
+        };
+
+        decoded.unwrap_or_else(|err| {
+            debug!("Failed to decode a {} event, falling back to its raw data. {}", event.as_str(), err);
+            DispatchEvent::raw(packet)
+        })
+    }
+
+    fn raw(packet: &ReceivePacket) -> Self {
+        DispatchEvent::Raw {
+            event: packet.t.clone(),
+            data: packet.d.clone()
+        }
     }
 }
 /// A set of possible Discord gateway opcodes.
@@ -407,7 +733,7 @@ pub enum Opcodes {
 }
 
 /// Codes that denote the cause of the gateway closing.
-#[derive(Debug, Copy, Deserialize_repr, Clone)]
+#[derive(Debug, Copy, Serialize_repr, Deserialize_repr, Clone, PartialEq)]
 #[repr(u16)]
 pub enum CloseCodes {
     /// The cause of the error is unknown.
@@ -423,7 +749,7 @@ pub enum CloseCodes {
     /// More than one identify payload was sent.
     AlreadyAuthenticated,
     /// The sequence sent when resuming the session was invalid.
-    InvalidSeq,
+    InvalidSeq = 4007,
     /// A ratelimit caused by sending payloads too quickly.
     Ratelimited,
     /// The session timed out, a reconnect is required.
@@ -432,4 +758,187 @@ pub enum CloseCodes {
     InvalidShard,
     /// The session would have had too many guilds, which indicated that sharding is required.
     ShardingRequired,
-}
\ No newline at end of file
+    /// An invalid version of the gateway was requested.
+    InvalidApiVersion,
+    /// Invalid intents were sent when identifying.
+    InvalidIntents,
+    /// Intents which the bot has not been approved for were sent when identifying.
+    DisallowedIntents,
+}
+
+impl CloseCodes {
+    /// Obtains the close code for the provided websocket close frame code, if it is a Discord close code.
+    pub fn from_code(code: u16) -> Option<Self> {
+        match code {
+            4000 => Some(CloseCodes::UnknownError),
+            4001 => Some(CloseCodes::UnknownOpcode),
+            4002 => Some(CloseCodes::DecodeError),
+            4003 => Some(CloseCodes::NotAuthenticated),
+            4004 => Some(CloseCodes::AuthenticationFailed),
+            4005 => Some(CloseCodes::AlreadyAuthenticated),
+            4007 => Some(CloseCodes::InvalidSeq),
+            4008 => Some(CloseCodes::Ratelimited),
+            4009 => Some(CloseCodes::SessionTimeout),
+            4010 => Some(CloseCodes::InvalidShard),
+            4011 => Some(CloseCodes::ShardingRequired),
+            4012 => Some(CloseCodes::InvalidApiVersion),
+            4013 => Some(CloseCodes::InvalidIntents),
+            4014 => Some(CloseCodes::DisallowedIntents),
+            _ => None
+        }
+    }
+
+    /// Whether or not the client should stop attempting to connect after receiving this close code.
+    pub fn is_fatal(&self) -> bool {
+        match self {
+            CloseCodes::AuthenticationFailed
+            | CloseCodes::InvalidShard
+            | CloseCodes::ShardingRequired
+            | CloseCodes::InvalidApiVersion
+            | CloseCodes::InvalidIntents
+            | CloseCodes::DisallowedIntents => true,
+            _ => false
+        }
+    }
+
+    /// Whether or not this close code invalidates the current session, meaning that the client must identify again rather than resume.
+    pub fn invalidates_session(&self) -> bool {
+        match self {
+            CloseCodes::NotAuthenticated | CloseCodes::InvalidSeq | CloseCodes::SessionTimeout => true,
+            _ => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DispatchEvent, GatewayEvent, IdentifyPacket, IdentifyProperties, Intents, ReceivePacket};
+
+    #[test]
+    fn unknown_events_keep_their_name() {
+        let packet: ReceivePacket = serde_json::from_str(r#"{"op":0,"s":4,"t":"FUTURE_EVENT","d":{"id":"1"}}"#).unwrap();
+        assert_eq!(packet.t, Some(GatewayEvent::Unknown(String::from("FUTURE_EVENT"))));
+
+        match DispatchEvent::from_packet(&packet) {
+            DispatchEvent::Raw { event, data } => {
+                assert_eq!(event, Some(GatewayEvent::Unknown(String::from("FUTURE_EVENT"))));
+                assert_eq!(data.get(), r#"{"id":"1"}"#);
+            },
+            other => panic!("Expected a raw dispatch, found {:?}", other)
+        };
+    }
+
+    const MESSAGE_CREATE: &str = r#"{"op":0,"s":42,"t":"MESSAGE_CREATE","d":{"type":0,"tts":false,"timestamp":"2019-04-02T21:03:56.913000+00:00","pinned":false,"nonce":"562447297815986176","mentions":[],"mention_roles":[],"mention_everyone":false,"member":{"roles":["265156286406983680"],"mute":false,"joined_at":"2016-12-25T04:10:21.427000+00:00","hoisted_role":null,"deaf":false},"id":"562447300907220992","embeds":[],"edited_timestamp":null,"content":"Hello!","channel_id":"381880193700069377","author":{"username":"Texlo","id":"218844420613734401","discriminator":"0001","avatar":null},"attachments":[],"guild_id":"81384788765712384"}}"#;
+    const GUILD_MEMBER_UPDATE: &str = r#"{"op":0,"s":43,"t":"GUILD_MEMBER_UPDATE","d":{"user":{"username":"Texlo","id":"218844420613734401","discriminator":"0001","avatar":"a_1269e74af4df7417b13759eae50c83dc"},"roles":["265156286406983680"],"premium_since":null,"nick":null,"guild_id":"81384788765712384"}}"#;
+    const GUILD_ROLE_DELETE: &str = r#"{"op":0,"s":44,"t":"GUILD_ROLE_DELETE","d":{"role_id":"265156286406983680","guild_id":"81384788765712384"}}"#;
+
+    fn decode(json: &str) -> DispatchEvent {
+        DispatchEvent::from_packet(&serde_json::from_str::<ReceivePacket>(json).unwrap())
+    }
+
+    #[test]
+    fn decodes_message_create() {
+        match decode(MESSAGE_CREATE) {
+            DispatchEvent::MessageCreate(message) => {
+                assert_eq!(message.id.0, 562447300907220992);
+                assert_eq!(message.content, "Hello!");
+                assert_eq!(message.author.username, "Texlo");
+                assert_eq!(message.member.map(|m| m.roles), Some(vec![String::from("265156286406983680")]));
+            },
+            other => panic!("Expected a message create event, found {:?}", other)
+        };
+    }
+
+    #[test]
+    fn decodes_guild_member_update() {
+        let missing_nick = GUILD_MEMBER_UPDATE.replace(r#""nick":null,"#, "");
+        let named = GUILD_MEMBER_UPDATE.replace(r#""nick":null"#, r#""nick":"Tex""#);
+
+        for (json, nick) in &[(GUILD_MEMBER_UPDATE, None), (missing_nick.as_str(), None), (named.as_str(), Some("Tex"))] {
+            match decode(json) {
+                DispatchEvent::GuildMemberUpdate(update) => {
+                    assert_eq!(update.guild_id.0, 81384788765712384);
+                    assert_eq!(update.user.id.0, 218844420613734401);
+                    assert_eq!(update.nick, nick.map(String::from));
+                },
+                other => panic!("Expected a guild member update event, found {:?}", other)
+            };
+        }
+    }
+
+    #[test]
+    fn decodes_guild_role_delete() {
+        match decode(GUILD_ROLE_DELETE) {
+            DispatchEvent::GuildRoleDelete(delete) => {
+                assert_eq!(delete.guild_id.0, 81384788765712384);
+                assert_eq!(delete.role_id.0, 265156286406983680);
+            },
+            other => panic!("Expected a guild role delete event, found {:?}", other)
+        };
+    }
+
+    #[test]
+    fn events_round_trip() {
+        for event in &[GatewayEvent::MESSAGE_CREATE, GatewayEvent::Unknown(String::from("FUTURE_EVENT"))] {
+            let json = serde_json::to_string(event).unwrap();
+            assert_eq!(json, format!("\"{}\"", event.as_str()));
+            assert_eq!(&serde_json::from_str::<GatewayEvent>(&json).unwrap(), event);
+        }
+        assert_eq!(GatewayEvent::from("MESSAGE_CREATE"), GatewayEvent::MESSAGE_CREATE);
+    }
+
+    fn identify(intents: Option<Intents>) -> serde_json::Value {
+        serde_json::to_value(IdentifyPacket {
+            token: String::from("Bot mock"),
+            properties: IdentifyProperties {
+                os: String::from("linux"),
+                browser: String::from("spectacles"),
+                device: String::from("spectacles")
+            },
+            version: 6,
+            compress: false,
+            large_threshold: 250,
+            shard: [0, 1],
+            presence: None,
+            intents
+        }).unwrap()
+    }
+
+    #[test]
+    fn identify_serializes_intents_as_bits() {
+        let packet = identify(Some(Intents::GUILDS | Intents::GUILD_MESSAGES));
+        assert_eq!(packet["intents"], serde_json::json!(513));
+        assert_eq!(serde_json::from_value::<Intents>(packet["intents"].clone()).unwrap(), Intents::GUILDS | Intents::GUILD_MESSAGES);
+
+        assert!(identify(None).get("intents").is_none());
+    }
+
+    #[test]
+    fn intents_enable_their_events() {
+        let events = Intents::GUILD_BANS.events();
+        assert_eq!(events, vec![GatewayEvent::GUILD_BAN_ADD, GatewayEvent::GUILD_BAN_REMOVE]);
+
+        // Pins in direct messages are only covered by the direct messages intent when the guilds intent does not already cover them.
+        let events = Intents::DIRECT_MESSAGES.events();
+        assert!(events.contains(&GatewayEvent::MESSAGE_CREATE));
+        assert!(events.contains(&GatewayEvent::CHANNEL_PINS_UPDATE));
+        assert!(!events.contains(&GatewayEvent::MESSAGE_DELETE_BULK));
+        let events = (Intents::GUILDS | Intents::DIRECT_MESSAGES).events();
+        assert_eq!(events.iter().filter(|e| **e == GatewayEvent::CHANNEL_PINS_UPDATE).count(), 1);
+
+        let events = Intents::GUILD_MESSAGES.events();
+        assert!(events.contains(&GatewayEvent::MESSAGE_CREATE));
+        assert!(events.contains(&GatewayEvent::MESSAGE_DELETE_BULK));
+        assert!(!events.contains(&GatewayEvent::CHANNEL_PINS_UPDATE));
+    }
+
+    #[test]
+    fn intents_enable_events_without_an_intent() {
+        let intents = Intents::GUILD_MEMBERS;
+        assert!(intents.enables(&GatewayEvent::GUILD_MEMBER_ADD));
+        assert!(!intents.enables(&GatewayEvent::MESSAGE_CREATE));
+        assert!(intents.enables(&GatewayEvent::READY));
+        assert!(intents.enables(&GatewayEvent::USER_UPDATE));
+        assert!(Intents::empty().enables(&GatewayEvent::Unknown(String::from("FUTURE_EVENT"))));
+    }
+}
@@ -106,6 +106,7 @@ pub struct UnavailableGuild {
     /// The guild ID of the guild.
     pub id: Snowflake,
     /// Whether or not the guild is available, usually set to true.
+    #[serde(default)]
     pub unavailable: bool
 }
 
@@ -424,7 +425,8 @@ pub struct GuildMemberUpdate {
     /// The user who was updated.
     pub user: User,
     /// The nickname of the user in the guild.
-    pub nick: String
+    #[serde(default)]
+    pub nick: Option<String>
 }
 
 /// Represents a response to a Guild Request Members packet.
@@ -433,7 +435,16 @@ pub struct GuildMembersChunk {
     /// The guild ID of the guild.
     pub guild_id: Snowflake,
     /// An array of guild member objects.
-    pub members: Vec<GuildMember>
+    pub members: Vec<GuildMember>,
+    /// The index of this chunk, out of every chunk sent for the request.
+    #[serde(default)]
+    pub chunk_index: u32,
+    /// The total amount of chunks sent for the request.
+    #[serde(default)]
+    pub chunk_count: u32,
+    /// The nonce of the request that this chunk was sent for, if one was provided.
+    #[serde(default)]
+    pub nonce: Option<String>
 }
 
 /// Represents a packet sent when a role is created ina  guild.
@@ -460,7 +471,7 @@ pub struct GuildRoleDelete {
     /// The guild ID of the guild.
     pub guild_id: Snowflake,
     /// The ID of the deleted role.
-    pub role: Snowflake
+    pub role_id: Snowflake
 }
 
 /// A guild's explicit content filter levels.
@@ -1,5 +1,7 @@
 //! A collection of data types for working with various Spectacles modules.
 
+#[macro_use] extern crate bitflags;
+#[macro_use] extern crate log;
 #[macro_use] extern crate serde_derive;
 
 pub use snowflake::*;
@@ -13,4 +15,5 @@ pub mod invite;
 pub mod gateway;
 pub mod presence;
 pub mod message;
-pub mod snowflake;
\ No newline at end of file
+pub mod snowflake;
+pub mod etf;
\ No newline at end of file
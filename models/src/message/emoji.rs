@@ -123,4 +123,47 @@ pub struct GuildEmojisUpdate {
     pub guild_id: Snowflake,
     /// The collion of guild emojis.
     pub emojis: Vec<Emoji>
-}
\ No newline at end of file
+}
+/// The gateway event emitted when a user reacts to a message.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MessageReactionAdd {
+    /// The ID of the user who reacted.
+    pub user_id: Snowflake,
+    /// The ID of the channel that the message is in.
+    pub channel_id: Snowflake,
+    /// The ID of the message.
+    pub message_id: Snowflake,
+    /// The ID of the guild, if the message is in a guild.
+    #[serde(default)]
+    pub guild_id: Option<Snowflake>,
+    /// The emoji that was used to react.
+    pub emoji: Emoji
+}
+
+/// The gateway event emitted when a user removes a reaction from a message.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MessageReactionRemove {
+    /// The ID of the user whose reaction was removed.
+    pub user_id: Snowflake,
+    /// The ID of the channel that the message is in.
+    pub channel_id: Snowflake,
+    /// The ID of the message.
+    pub message_id: Snowflake,
+    /// The ID of the guild, if the message is in a guild.
+    #[serde(default)]
+    pub guild_id: Option<Snowflake>,
+    /// The emoji that was removed.
+    pub emoji: Emoji
+}
+
+/// The gateway event emitted when every reaction is removed from a message.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MessageReactionRemoveAll {
+    /// The ID of the channel that the message is in.
+    pub channel_id: Snowflake,
+    /// The ID of the message.
+    pub message_id: Snowflake,
+    /// The ID of the guild, if the message is in a guild.
+    #[serde(default)]
+    pub guild_id: Option<Snowflake>
+}
@@ -253,6 +253,30 @@ impl ChannelMessagesQuery {
 }
 
 
+/// Represents a packet sent by the gateway when a message is deleted.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct MessageDelete {
+    /// The ID of the deleted message.
+    pub id: Snowflake,
+    /// The ID of the channel that the message was in.
+    pub channel_id: Snowflake,
+    /// The ID of the guild, if the message was in a guild.
+    #[serde(default)]
+    pub guild_id: Option<Snowflake>
+}
+
+/// Represents a packet sent by the gateway when several messages are deleted at once.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct MessageDeleteBulk {
+    /// The IDs of the deleted messages.
+    pub ids: Vec<Snowflake>,
+    /// The ID of the channel that the messages were in.
+    pub channel_id: Snowflake,
+    /// The ID of the guild, if the messages were in a guild.
+    #[serde(default)]
+    pub guild_id: Option<Snowflake>
+}
+
 /// Represents an attachment sent by a user.
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct MessageAttachment {
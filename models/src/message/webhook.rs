@@ -106,3 +106,12 @@ impl ExecuteWebhookOptions {
         self
     }
 }
+
+/// Represents a packet sent by the gateway when a channel's webhooks are created, updated or deleted.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct WebhooksUpdate {
+    /// The ID of the guild.
+    pub guild_id: Snowflake,
+    /// The ID of the channel.
+    pub channel_id: Snowflake
+}
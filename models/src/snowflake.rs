@@ -41,7 +41,24 @@ impl <'de> Visitor<'de> for SnowflakeVisitor {
     type Value = Snowflake;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("A snowflake as a string.")
+        formatter.write_str("A snowflake as a string or an integer.")
+    }
+
+    fn visit_u64<E>(self, value: u64) -> std::result::Result<Self::Value, E>
+        where
+            E: de::Error,
+    {
+        Ok(Snowflake(value))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> std::result::Result<Self::Value, E>
+        where
+            E: de::Error,
+    {
+        if value < 0 {
+            return Err(de::Error::invalid_value(de::Unexpected::Signed(value), &self));
+        };
+        Ok(Snowflake(value as u64))
     }
 
     fn visit_str<E>(self, value: &str) -> std::result::Result<Self::Value, E>
@@ -58,6 +75,6 @@ impl<'de> Deserialize<'de> for Snowflake {
         where
             D: Deserializer<'de>,
     {
-        deserializer.deserialize_identifier(SnowflakeVisitor)
+        deserializer.deserialize_any(SnowflakeVisitor)
     }
 }
\ No newline at end of file
@@ -44,4 +44,14 @@ pub struct VoiceRegion {
     pub deprecated: bool,
     /// Whether or not this is a custom voice region.
     pub custom: bool
-}
\ No newline at end of file
+}
+/// Represents a packet sent by the gateway when a guild's voice server is updated.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VoiceServerUpdate {
+    /// The voice connection token.
+    pub token: String,
+    /// The ID of the guild that this voice server belongs to.
+    pub guild_id: Snowflake,
+    /// The host of the voice server, if one is available.
+    pub endpoint: Option<String>
+}
@@ -1 +1,7 @@
-pub const BASE_URL: &'static str = "https://discordapp.com/api/v7";
\ No newline at end of file
+use std::time::Duration;
+
+pub const BASE_URL: &'static str = "https://discordapp.com/api/v7";
+/// The time after which a queued request is released, if the reset of its bucket is unknown.
+pub const RELEASE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The longest delay before retrying a request after a server error.
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
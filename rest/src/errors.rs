@@ -4,25 +4,200 @@ use std::{
     io::Error as IoError,
     num::ParseIntError,
     result::Result as StdResult,
+    time::Duration,
 };
 
+use hashbrown::HashMap;
+use redis::RedisError;
 use reqwest::Error as ReqwestError;
 use reqwest::StatusCode;
-use serde_json::Error as JsonError;
+use serde_json::{Error as JsonError, Value};
 use tokio::timer::Error as TimerError;
 
 /// A modified result type which encompasses the global error type.
 pub type Result<T> = StdResult<T, Error>;
 
+macro_rules! error_codes {
+    ($($(#[$attr:meta])* $name:ident = $code:literal,)*) => {
+        /// A JSON error code returned by the Discord API.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum DiscordErrorCode {
+            $($(#[$attr])* $name,)*
+            /// An error code which is not covered by this enum.
+            Other(i32),
+        }
+
+        impl DiscordErrorCode {
+            /// The numeric value of this error code.
+            pub fn code(&self) -> i32 {
+                match self {
+                    $(DiscordErrorCode::$name => $code,)*
+                    DiscordErrorCode::Other(code) => *code,
+                }
+            }
+        }
+
+        impl From<i32> for DiscordErrorCode {
+            fn from(code: i32) -> Self {
+                match code {
+                    $($code => DiscordErrorCode::$name,)*
+                    other => DiscordErrorCode::Other(other),
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    /// A general error, which does not have a more specific code.
+    General = 0,
+    UnknownAccount = 10001,
+    UnknownApplication = 10002,
+    UnknownChannel = 10003,
+    UnknownGuild = 10004,
+    UnknownIntegration = 10005,
+    UnknownInvite = 10006,
+    UnknownMember = 10007,
+    UnknownMessage = 10008,
+    UnknownOverwrite = 10009,
+    UnknownProvider = 10010,
+    UnknownRole = 10011,
+    UnknownToken = 10012,
+    UnknownUser = 10013,
+    UnknownEmoji = 10014,
+    UnknownWebhook = 10015,
+    /// Bots cannot use this endpoint.
+    BotsCannotUseEndpoint = 20001,
+    /// Only bots can use this endpoint.
+    OnlyBotsCanUseEndpoint = 20002,
+    /// The maximum number of guilds has been reached.
+    MaxGuilds = 30001,
+    /// The maximum number of friends has been reached.
+    MaxFriends = 30002,
+    /// The maximum number of pins for the channel has been reached.
+    MaxPins = 30003,
+    /// The maximum number of guild roles has been reached.
+    MaxRoles = 30005,
+    /// The maximum number of reactions has been reached.
+    MaxReactions = 30010,
+    /// The maximum number of guild channels has been reached.
+    MaxChannels = 30013,
+    Unauthorized = 40001,
+    MissingAccess = 50001,
+    InvalidAccountType = 50002,
+    /// The action cannot be executed on a DM channel.
+    CannotExecuteOnDm = 50003,
+    WidgetDisabled = 50004,
+    /// A message authored by another user cannot be edited.
+    CannotEditOtherUsersMessage = 50005,
+    CannotSendEmptyMessage = 50006,
+    /// Messages cannot be sent to this user, usually because they have DMs disabled.
+    CannotSendMessagesToUser = 50007,
+    CannotSendMessagesInVoiceChannel = 50008,
+    /// The channel's verification level is too high.
+    ChannelVerificationTooHigh = 50009,
+    MissingPermissions = 50013,
+    InvalidAuthToken = 50014,
+    NoteTooLong = 50015,
+    /// Too few or too many messages were provided to delete.
+    InvalidBulkDeleteCount = 50016,
+    /// A message can only be pinned to the channel it was sent in.
+    InvalidPinChannel = 50019,
+    InvalidInviteCode = 50020,
+    /// System messages cannot be acted upon.
+    CannotExecuteOnSystemMessage = 50021,
+    /// A message provided was too old to bulk delete.
+    MessageTooOld = 50034,
+    /// The request body failed validation, with the invalid fields provided in `APIError::errors`.
+    InvalidFormBody = 50035,
+    /// An invite was accepted to a guild that the application's bot is not in.
+    InviteAcceptedToGuildWithoutBot = 50036,
+    /// The reaction was blocked.
+    ReactionBlocked = 90001,
+}
+
+/// A validation error for a single field of a request body.
+#[derive(Deserialize, Debug, Clone)]
+pub struct FieldError {
+    /// The validation error code, such as `BASE_TYPE_MAX_LENGTH`.
+    pub code: String,
+    /// A description of the validation error.
+    pub message: String,
+}
+
+/// The body of an error response from the Discord API.
+#[derive(Deserialize, Debug, Default)]
+pub(crate) struct ErrorResponse {
+    #[serde(default)]
+    code: i32,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    errors: Option<Value>,
+}
+
 /// An HTTP error encountered as a result of a request sent to the Discord API.
 #[derive(Debug)]
 pub struct APIError {
     /// The error message returned my Discord.
     pub message: String,
     /// The error code returned by Discord.
-    pub code: i32,
+    pub code: DiscordErrorCode,
     /// The HTTP status code of the request.
     pub http_status: StatusCode,
+    /// The validation errors of each invalid field in the request body, keyed by the field's path, such as `embed.fields.0.name`.
+    pub errors: HashMap<String, Vec<FieldError>>,
+}
+
+impl APIError {
+    /// Creates an API error from an error response, or from the status alone if the response body could not be parsed.
+    pub(crate) fn new(http_status: StatusCode, body: Option<ErrorResponse>) -> Self {
+        let body = body.unwrap_or_default();
+        let mut errors = HashMap::new();
+        if let Some(ref value) = body.errors {
+            flatten_errors(value, "", &mut errors);
+        };
+        let message = if body.message.is_empty() {
+            http_status.canonical_reason().unwrap_or("Unknown error").to_string()
+        } else {
+            body.message
+        };
+
+        Self {
+            message,
+            code: DiscordErrorCode::from(body.code),
+            http_status,
+            errors,
+        }
+    }
+}
+
+/// Collects the `_errors` arrays of a nested error object, keyed by the path of their field.
+fn flatten_errors(value: &Value, path: &str, out: &mut HashMap<String, Vec<FieldError>>) {
+    if let Value::Object(map) = value {
+        for (key, value) in map {
+            if key == "_errors" {
+                if let Ok(errors) = serde_json::from_value::<Vec<FieldError>>(value.clone()) {
+                    out.entry(path.to_string()).or_insert_with(Vec::new).extend(errors);
+                };
+            } else if path.is_empty() {
+                flatten_errors(value, key, out);
+            } else {
+                flatten_errors(value, &format!("{}.{}", path, key), out);
+            };
+        }
+    };
+}
+
+/// A rate limit which was reached by a request, as reported by a 429 response.
+#[derive(Debug, Clone)]
+pub struct RatelimitError {
+    /// The time to wait before retrying the request.
+    pub retry_after: Duration,
+    /// Whether or not the global rate limit was reached.
+    pub global: bool,
+    /// The hash of the bucket which was exhausted, if reported by Discord.
+    pub bucket: Option<String>,
 }
 
 impl StdError for APIError {
@@ -46,6 +221,52 @@ pub enum Error {
     Reqwest(ReqwestError),
     InvalidTokenError,
     Io(IoError),
+    Redis(RedisError),
+    Ratelimited(RatelimitError),
+}
+
+impl Error {
+    /// The Discord API error, if this error was returned by Discord.
+    pub fn api_error(&self) -> Option<&APIError> {
+        match self {
+            Error::Discord(e) => Some(e),
+            _ => None
+        }
+    }
+
+    /// The JSON error code returned by Discord, if any.
+    pub fn error_code(&self) -> Option<DiscordErrorCode> {
+        self.api_error().map(|e| e.code)
+    }
+
+    /// Whether or not the requested resource does not exist.
+    pub fn is_not_found(&self) -> bool {
+        self.api_error().map_or(false, |e| {
+            let code = e.code.code();
+            e.http_status == StatusCode::NOT_FOUND || (code > 10000 && code < 20000)
+        })
+    }
+
+    /// Whether or not the client lacks the permissions or access required for the request.
+    pub fn is_missing_permissions(&self) -> bool {
+        match self.error_code() {
+            Some(DiscordErrorCode::MissingPermissions) | Some(DiscordErrorCode::MissingAccess) => true,
+            _ => false
+        }
+    }
+
+    /// Whether or not the request was rate limited, and was not retried.
+    pub fn is_ratelimited(&self) -> bool {
+        match self {
+            Error::Ratelimited(_) => true,
+            _ => false
+        }
+    }
+
+    /// Whether or not the request body failed validation, in which case the invalid fields are provided in `APIError::errors`.
+    pub fn is_validation_error(&self) -> bool {
+        self.error_code() == Some(DiscordErrorCode::InvalidFormBody)
+    }
 }
 
 impl Display for Error {
@@ -63,6 +284,9 @@ impl StdError for Error {
             Error::Timer(e) => e.description(),
             Error::Io(e) => e.description(),
             Error::Json(e) => e.description(),
+            Error::Redis(e) => e.description(),
+            Error::Ratelimited(e) if e.global => "The global rate limit was reached, and the request was not retried.",
+            Error::Ratelimited(_) => "A route rate limit was reached, and the request was not retried.",
             Error::InvalidTokenError =>
                 "The token provided was not accepted by Discord. Please check that your token is correct and try again."
         }
@@ -104,4 +328,10 @@ impl From<ParseIntError> for Error {
     fn from(err: ParseIntError) -> Self {
         Error::ParseInt(err)
     }
+}
+
+impl From<RedisError> for Error {
+    fn from(err: RedisError) -> Self {
+        Error::Redis(err)
+    }
 }
\ No newline at end of file
@@ -55,7 +55,25 @@
 //!     .set_proxy(proxy);
 //!
 //! ```
-//! More rate limiting strategies will be considered in future.
+//! Alternatively, rate limits may be shared between processes through Redis, without an extra HTTP hop, using the [`RedisRatelimiter`].
+//! ```rust,no_run
+//! use futures::Future;
+//! use spectacles_rest::{RedisRatelimiter, RestClient};
+//!
+//! let token = std::env::var("DISCORD_TOKEN").expect("Failed to parse token");
+//! tokio::run(redis::Client::open("redis://127.0.0.1").unwrap()
+//!     .get_shared_async_connection()
+//!     .map(move |conn| {
+//!         let rest = RestClient::new(token, false)
+//!             .set_ratelimiter(RedisRatelimiter::new(conn, "ratelimit"));
+//!     })
+//!     .map_err(|err| eprintln!("Failed to connect to Redis. {:?}", err))
+//! );
+//! ```
+//! Custom rate limiting strategies may be used by implementing the [`RatelimitBackend`] trait.
+//!
+//! [`RedisRatelimiter`]: struct.RedisRatelimiter.html
+//! [`RatelimitBackend`]: trait.RatelimitBackend.html
 //!
 //!
 //! ## Installation
@@ -65,6 +83,8 @@
 //! spectacles-rest = "0.1.0"
 //!
 
+#[macro_use]
+extern crate lazy_static;
 #[macro_use]
 extern crate log;
 #[macro_use]
@@ -73,23 +93,28 @@ extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
 
+use std::cmp;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use futures::future::{Future, Loop};
+use futures::future::{self, Future, Loop};
 use http::header::HeaderValue;
-use parking_lot::Mutex;
 use reqwest::header::HeaderMap;
 use reqwest::Method;
 use reqwest::r#async::{
     Client as ReqwestClient,
     ClientBuilder,
-    multipart::Form,
+    multipart::{Form, Part},
+    Response,
 };
 use serde::de::DeserializeOwned;
 use serde::ser::Serialize;
 use serde_json::Value;
+use tokio::timer::Delay;
 
 pub(crate) use ratelimit::*;
+pub use ratelimit::{InMemoryRatelimiter, RatelimitBackend, RatelimitInfo};
+pub use redis_ratelimit::RedisRatelimiter;
 use spectacles_model::channel::Channel;
 use spectacles_model::guild::{CreateGuildOptions, Guild};
 use spectacles_model::invite::Invite;
@@ -99,12 +124,15 @@ use spectacles_model::voice::VoiceRegion;
 /// A collection of interfaces for endpoint-specific Discord objects.
 pub use views::*;
 
-pub use crate::errors::{Error, Result};
+pub use crate::errors::{APIError, DiscordErrorCode, Error, FieldError, RatelimitError, Result};
 
 mod errors;
 mod ratelimit;
+mod redis_ratelimit;
 mod views;
 mod constants;
+#[cfg(feature = "mock")]
+pub mod mock;
 
 /// The Main client which is used to interface with the various components of the Discord API.
 #[derive(Clone, Debug)]
@@ -114,7 +142,11 @@ pub struct RestClient {
     /// The base URL of the client. This may be changed to accomodate an external proxy system.
     pub base_url: String,
     http: ReqwestClient,
-    ratelimiter: Option<Arc<Mutex<Ratelimter>>>,
+    ratelimiter: Option<Arc<RatelimitBackend>>,
+    /// Whether or not requests are routed through a rate limiting proxy.
+    proxy: bool,
+    /// The retry options set by the caller, if any.
+    retry: Option<RetryOptions>,
 }
 
 impl RestClient {
@@ -140,13 +172,15 @@ impl RestClient {
 
         let mut rest = RestClient {
             token,
-            http: client.clone(),
+            http: client,
             base_url: constants::BASE_URL.to_string(),
             ratelimiter: None,
+            proxy: false,
+            retry: None,
         };
 
         if using_ratelimiter {
-            rest.ratelimiter = Some(Arc::new(Mutex::new(Ratelimter::new(client))));
+            rest.ratelimiter = Some(Arc::new(InMemoryRatelimiter::new()));
         };
 
         rest
@@ -154,8 +188,38 @@ impl RestClient {
 
     /// Enables support for routing all requests though an HTTP rate limiting proxy.
     /// If you plan on making distributed REST requests, an HTTP proxy is recommended for handling rate limits in a distributed manner.
+    /// As the proxy is responsible for rate limits, rate limited requests are no longer retried by default, and fail with `Error::Ratelimited` instead.
+    /// Retry options which are set with `set_retry_options()` take precedence over this default.
     pub fn set_proxy(mut self, url: String) -> Self {
         self.base_url = url;
+        self.proxy = true;
+        self
+    }
+
+    /// Sets the options which determine how failed requests are retried.
+    pub fn set_retry_options(mut self, options: RetryOptions) -> Self {
+        self.retry = Some(options);
+        self
+    }
+
+    /// The options which determine how failed requests are retried, taking the proxy into account if none were set.
+    fn retry_options(&self) -> RetryOptions {
+        match self.retry {
+            Some(options) => options,
+            None if self.proxy => RetryOptions {
+                ratelimit_retries: 0,
+                ..RetryOptions::default()
+            },
+            None => RetryOptions::default()
+        }
+    }
+
+    /// Sets the rate limiter which is used to rate limit requests to the Discord API.
+    /// A shared rate limiter, such as a [`RedisRatelimiter`], may be used to share rate limits between processes.
+    ///
+    /// [`RedisRatelimiter`]: struct.RedisRatelimiter.html
+    pub fn set_ratelimiter<R: RatelimitBackend + 'static>(mut self, ratelimiter: R) -> Self {
+        self.ratelimiter = Some(Arc::new(ratelimiter));
         self
     }
 
@@ -248,81 +312,122 @@ impl RestClient {
 
     /// Makes an HTTP request to the provided Discord API endpoint.
     /// Depending on the ratelimiter status, the request may or may not be rate limited.
-    pub fn request<T>(&self, mut endpt: Endpoint) -> Box<Future<Item=T, Error=Error> + Send>
+    pub fn request<T>(&self, endpt: Endpoint) -> Box<Future<Item=T, Error=Error> + Send>
         where T: DeserializeOwned + Send + 'static
     {
-        if let Some(ref rl) = self.ratelimiter {
-            let http = self.http.clone();
-            let base = self.base_url.clone();
-            Box::new(futures::future::loop_fn(Arc::clone(rl), move |ratelimit| {
-                let req_url = format!("{}{}", base, &endpt.url);
-                let route = Bucket::make_route(endpt.method.clone(), req_url.clone());
-                let mut req = http.request(endpt.method.clone(), &req_url)
-                    .query(&endpt.query)
-                    .json(&endpt.json);
-                if endpt.multipart.is_some() {
-                    let form = endpt.multipart.take().unwrap();
-                    req = req.multipart(form);
-                };
-                let limiter = Arc::clone(&ratelimit);
-                let limiter_2 = Arc::clone(&limiter);
-                ratelimit.lock().enqueue(route.clone())
-                    .and_then(|_| req.send().from_err())
-                    .and_then(move |resp| limiter.lock().handle_resp(route, resp))
-                    .map(move |status| match status {
-                        ResponseStatus::Success(resp) => Loop::Break(resp),
-                        ResponseStatus::Ratelimited | ResponseStatus::ServerError => Loop::Continue(limiter_2)
-                    })
-            }).and_then(|mut resp| resp.json().from_err()))
-        } else {
-            let req_url = format!("{}{}", self.base_url, &endpt.url);
-            let req = self.http.request(endpt.method.clone(), &req_url)
-                .query(&endpt.query)
-                .json(&endpt.json);
-            Box::new(req.send().map_err(Error::from)
-                .and_then(|mut resp| resp.json().from_err())
-            )
-        }
+        Box::new(self.send(endpt).and_then(|mut resp| resp.json().from_err()))
     }
 
     /// Similar to the above method, but does not attempt to deserialize a JSON payload from the request.
     /// Use this method if you are dealing with routes that return 204 (No content).
-    pub fn request_empty(&self, mut endpt: Endpoint) -> Box<Future<Item=(), Error=Error> + Send> {
-        if let Some(ref rl) = self.ratelimiter {
-            let http = self.http.clone();
-            let base = self.base_url.clone();
-            Box::new(futures::future::loop_fn(Arc::clone(rl), move |ratelimit| {
-                let req_url = format!("{}{}", base, &endpt.url);
-                let route = Bucket::make_route(endpt.method.clone(), req_url.clone());
-                let mut req = http.request(endpt.method.clone(), &req_url)
-                    .query(&endpt.query)
-                    .json(&endpt.json);
-                if endpt.multipart.is_some() {
-                    let form = endpt.multipart.take().unwrap();
-                    req = req.multipart(form);
-                };
-                let limiter = Arc::clone(&ratelimit);
-                let limiter_2 = Arc::clone(&limiter);
-                ratelimit.lock().enqueue(route.clone())
-                    .and_then(|_| req.send().from_err())
-                    .and_then(move |resp| limiter.lock().handle_resp(route, resp))
-                    .map(move |status| match status {
-                        ResponseStatus::Success(resp) => Loop::Break(resp),
-                        ResponseStatus::Ratelimited | ResponseStatus::ServerError => Loop::Continue(limiter_2)
-                    })
-            }).map(|_| ()))
-        } else {
-            let req_url = format!("{}{}", self.base_url, &endpt.url);
-            let req = self.http.request(endpt.method.clone(), &req_url)
-                .query(&endpt.query)
-                .json(&endpt.json);
-            Box::new(req.send().map_err(Error::from)
-                .map(|_| ())
-            )
+    pub fn request_empty(&self, endpt: Endpoint) -> Box<Future<Item=(), Error=Error> + Send> {
+        Box::new(self.send(endpt).map(|_| ()))
+    }
+
+    /// Sends a request to the provided endpoint, resolving with the response once it has succeeded.
+    /// Rate limited requests and server errors are retried according to the client's retry options.
+    fn send(&self, mut endpt: Endpoint) -> impl Future<Item=Response, Error=Error> {
+        let http = self.http.clone();
+        let base = self.base_url.clone();
+        let limiter = self.ratelimiter.clone();
+        let retry = self.retry_options();
+        let route = Bucket::make_route(&endpt.method, &endpt.url);
+        // A custom multipart form can only be sent once, so requests which use one are never retried.
+        let mut form = endpt.multipart.take();
+        let retryable = form.is_none();
+
+        future::loop_fn((endpt, Attempts::default()), move |(endpt, attempts)| {
+            let req = http.request(endpt.method.clone(), &format!("{}{}", base, &endpt.url))
+                .query(&endpt.query);
+            let req = match form.take() {
+                Some(form) => req.multipart(form),
+                None if !endpt.files.is_empty() => req.multipart(endpt.form()),
+                None => req.json(&endpt.json)
+            };
+            let acquire: Box<Future<Item=(), Error=Error> + Send> = match limiter {
+                Some(ref limiter) => limiter.acquire(&route),
+                None => Box::new(future::ok(()))
+            };
+            let has_limiter = limiter.is_some();
+            let limiter = limiter.clone();
+            let route = route.clone();
+
+            acquire.and_then(move |_| req.send().from_err())
+                .and_then(move |resp| handle_resp(limiter, route, resp))
+                .and_then(move |status| -> Box<Future<Item=Loop<Response, (Endpoint, Attempts)>, Error=Error> + Send> {
+                    match status {
+                        ResponseStatus::Success(resp) => Box::new(future::ok(Loop::Break(resp))),
+                        ResponseStatus::Ratelimited(err) => {
+                            if !retryable || attempts.ratelimited >= retry.ratelimit_retries {
+                                return Box::new(future::err(Error::Ratelimited(err)));
+                            };
+                            // The rate limiter delays the retry by itself.
+                            let wait = if has_limiter { Duration::from_secs(0) } else { err.retry_after };
+                            let attempts = Attempts { ratelimited: attempts.ratelimited + 1, ..attempts };
+                            Box::new(Delay::new(Instant::now() + wait)
+                                .from_err()
+                                .map(move |_| Loop::Continue((endpt, attempts)))
+                            )
+                        },
+                        ResponseStatus::ServerError(err) => {
+                            if !retryable || attempts.server_errors >= retry.server_error_retries {
+                                return Box::new(future::err(Error::Discord(err)));
+                            };
+                            let wait = retry.backoff_for(attempts.server_errors);
+                            warn!("Discord returned a server error ({}), retrying in {:?}.", err.http_status, wait);
+                            let attempts = Attempts { server_errors: attempts.server_errors + 1, ..attempts };
+                            Box::new(Delay::new(Instant::now() + wait)
+                                .from_err()
+                                .map(move |_| Loop::Continue((endpt, attempts)))
+                            )
+                        }
+                    }
+                })
+        })
+    }
+}
+
+/// Options which determine how failed requests are retried.
+///
+/// Requests with a custom multipart form, set with `Endpoint::multipart()`, are never retried, as the form can only be sent once.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryOptions {
+    /// The maximum amount of times a request is retried after being rate limited.
+    /// Once exhausted, the request fails with `Error::Ratelimited`.
+    pub ratelimit_retries: u32,
+    /// The maximum amount of times a request is retried after a server error.
+    pub server_error_retries: u32,
+    /// The delay before retrying a request after a server error, which doubles with every further retry.
+    /// The delay never exceeds 60 seconds.
+    pub backoff: Duration,
+}
+
+impl RetryOptions {
+    /// The delay before retrying a request which has already been retried the provided amount of times after server errors.
+    fn backoff_for(&self, retries: u32) -> Duration {
+        2u32.checked_pow(retries)
+            .and_then(|factor| self.backoff.checked_mul(factor))
+            .map_or(constants::MAX_BACKOFF, |wait| cmp::min(wait, constants::MAX_BACKOFF))
+    }
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self {
+            ratelimit_retries: 5,
+            server_error_retries: 3,
+            backoff: Duration::from_secs(1),
         }
     }
 }
 
+/// The amount of times a request has been retried.
+#[derive(Clone, Copy, Debug, Default)]
+struct Attempts {
+    ratelimited: u32,
+    server_errors: u32,
+}
+
 /// A structure representing a Discord API endpoint, in the context of an HTTP request.
 #[derive(Debug)]
 pub struct Endpoint {
@@ -331,6 +436,15 @@ pub struct Endpoint {
     json: Option<Value>,
     query: Option<Value>,
     multipart: Option<Form>,
+    files: Vec<Upload>,
+}
+
+/// A file which is uploaded alongside a request.
+#[derive(Debug)]
+struct Upload {
+    field: String,
+    file_name: String,
+    bytes: Vec<u8>,
 }
 
 impl Endpoint {
@@ -342,6 +456,7 @@ impl Endpoint {
             json: None,
             query: None,
             multipart: None,
+            files: Vec::new(),
         }
     }
 
@@ -366,8 +481,94 @@ impl Endpoint {
     }
 
     /// Adds a multipart form to the endpoint, which is useful for sending files to the Discord API.
+    /// As a form can only be sent once, requests with a form are not retried. Prefer `file()` for uploading files.
     pub fn multipart(mut self, payload: Form) -> Endpoint {
         self.multipart = Some(payload);
         self
     }
+
+    /// Adds a file to the endpoint, which is uploaded in a multipart form field.
+    /// The JSON body of the endpoint, if any, is sent in the `payload_json` field of the form.
+    pub fn file(mut self, field: &str, file_name: String, bytes: Vec<u8>) -> Endpoint {
+        self.files.push(Upload {
+            field: field.to_string(),
+            file_name,
+            bytes,
+        });
+        self
+    }
+
+    /// Builds a multipart form from the endpoint's files and JSON body.
+    fn form(&self) -> Form {
+        let mut form = Form::new();
+        for file in &self.files {
+            form = form.part(file.field.clone(), Part::bytes(file.bytes.clone()).file_name(file.file_name.clone()));
+        }
+        if let Some(ref json) = self.json {
+            form = form.part("payload_json", Part::text(json.to_string()));
+        };
+
+        form
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures::future::{self, Future};
+    use parking_lot::Mutex;
+    use reqwest::Method;
+    use tokio::runtime::current_thread::Runtime;
+
+    use crate::mock::MockApi;
+
+    use super::{Endpoint, RestClient, RetryOptions};
+
+    #[test]
+    fn burst_waits_for_bucket_in_order() {
+        let mut rt = Runtime::new().unwrap();
+        let api = rt.block_on(future::lazy(|| MockApi::start(1, Duration::from_millis(500)))).unwrap();
+        let mut client = RestClient::new(String::from("mock-token"), true);
+        client.base_url = api.base_url();
+
+        let completed = Arc::new(Mutex::new(Vec::new()));
+        let requests: Vec<_> = (0..5).map(|i| {
+            let completed = Arc::clone(&completed);
+            client.request_empty(Endpoint::new(Method::GET, String::from("/channels/223704706495545344/messages")))
+                .map(move |_| completed.lock().push(i))
+        }).collect();
+        rt.block_on(future::join_all(requests)).unwrap();
+
+        assert_eq!(api.requests(), 5);
+        assert_eq!(api.ratelimited(), 0);
+        assert_eq!(*completed.lock(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn proxy_keeps_retry_options() {
+        let client = RestClient::new(String::from("mock-token"), false);
+        assert_eq!(client.clone().set_proxy(String::from("http://localhost")).retry_options().ratelimit_retries, 0);
+
+        let options = RetryOptions {
+            ratelimit_retries: 2,
+            ..RetryOptions::default()
+        };
+        let retried = |client: RestClient| client.retry_options().ratelimit_retries;
+        assert_eq!(retried(client.clone().set_retry_options(options).set_proxy(String::from("http://localhost"))), 2);
+        assert_eq!(retried(client.set_proxy(String::from("http://localhost")).set_retry_options(options)), 2);
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let retry = RetryOptions { backoff: Duration::from_millis(500), ..RetryOptions::default() };
+        assert_eq!(retry.backoff_for(0), Duration::from_millis(500));
+        assert_eq!(retry.backoff_for(3), Duration::from_secs(4));
+        assert_eq!(retry.backoff_for(10), Duration::from_secs(60));
+        assert_eq!(retry.backoff_for(40), Duration::from_secs(60));
+
+        let retry = RetryOptions { backoff: Duration::from_secs(u64::max_value()), ..RetryOptions::default() };
+        assert_eq!(retry.backoff_for(1), Duration::from_secs(60));
+    }
 }
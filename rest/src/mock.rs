@@ -0,0 +1,127 @@
+//! A local HTTP server which imitates Discord's rate limits, for testing rate limiters without making requests to Discord.
+//!
+//! Every request shares a single bucket, which allows a fixed amount of requests per window.
+//! Requests within the limit are answered with an empty JSON object, and the rest are answered with a 429.
+
+use std::{
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant}
+};
+
+use futures::{Future, Stream};
+use parking_lot::Mutex;
+use tokio::{
+    io,
+    net::{TcpListener, TcpStream}
+};
+
+use crate::errors::Result;
+
+/// A local HTTP server, which answers requests with Discord's rate limit headers.
+pub struct MockApi {
+    /// The address of the server.
+    pub addr: SocketAddr,
+    state: Arc<Mutex<MockState>>
+}
+
+struct MockState {
+    limit: i64,
+    window: Duration,
+    window_start: Instant,
+    used: i64,
+    requests: usize,
+    ratelimited: usize,
+}
+
+impl MockApi {
+    /// Starts a mock API on a local port chosen by the OS, which allows the provided amount of requests per window.
+    /// This must be called from within a Tokio runtime.
+    pub fn start(limit: i64, window: Duration) -> Result<Self> {
+        let localhost: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let listener = TcpListener::bind(&localhost)?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(MockState {
+            limit,
+            window,
+            window_start: Instant::now(),
+            used: 0,
+            requests: 0,
+            ratelimited: 0,
+        }));
+
+        let server_state = Arc::clone(&state);
+        tokio::spawn(listener.incoming()
+            .map_err(|err| error!("[Mock] Failed to accept connection. {:?}", err))
+            .for_each(move |socket| {
+                tokio::spawn(MockApi::respond(Arc::clone(&server_state), socket));
+                Ok(())
+            })
+        );
+
+        Ok(Self { addr, state })
+    }
+
+    /// The base URL of the mock API, which may be used in place of Discord's.
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// The amount of requests that the mock API has received.
+    pub fn requests(&self) -> usize {
+        self.state.lock().requests
+    }
+
+    /// The amount of requests that the mock API has answered with a 429.
+    pub fn ratelimited(&self) -> usize {
+        self.state.lock().ratelimited
+    }
+
+    fn respond(state: Arc<Mutex<MockState>>, socket: TcpStream) -> impl Future<Item = (), Error = ()> {
+        io::read(socket, vec![0; 4096])
+            .and_then(move |(socket, _, _)| {
+                let response = state.lock().next_response();
+                io::write_all(socket, response.into_bytes())
+            })
+            .map(|_| ())
+            .map_err(|err| warn!("[Mock] Failed to answer request. {:?}", err))
+    }
+}
+
+impl MockState {
+    /// Counts a request against the bucket, and creates the HTTP response for it.
+    fn next_response(&mut self) -> String {
+        let now = Instant::now();
+        if now - self.window_start >= self.window {
+            self.window_start = now;
+            self.used = 0;
+        };
+        self.requests += 1;
+        let reset_after = self.window - (now - self.window_start);
+        let reset_secs = reset_after.as_secs() as f64 + f64::from(reset_after.subsec_millis()) / 1000.0;
+
+        let (status, body) = if self.used < self.limit {
+            self.used += 1;
+            ("200 OK", String::from("{}"))
+        } else {
+            self.ratelimited += 1;
+            let retry_after = reset_after.as_secs() * 1000 + u64::from(reset_after.subsec_millis());
+            ("429 Too Many Requests", json!({
+                "message": "You are being rate limited.",
+                "retry_after": retry_after,
+                "global": false
+            }).to_string())
+        };
+
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\
+            X-RateLimit-Bucket: mock\r\nX-RateLimit-Limit: {}\r\nX-RateLimit-Remaining: {}\r\nX-RateLimit-Reset-After: {:.3}\r\n\r\n{}",
+            status,
+            body.len(),
+            self.limit,
+            self.limit - self.used,
+            reset_secs,
+            body
+        )
+    }
+}
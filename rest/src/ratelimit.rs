@@ -1,22 +1,25 @@
-use std::borrow::Cow;
+use std::cmp;
 use std::collections::vec_deque::VecDeque;
+use std::fmt::Debug;
 use std::ops::Sub;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use chrono::{DateTime, Utc};
-use chrono::offset::TimeZone;
-use futures::Future;
-use futures::sync::oneshot::{self, Receiver, Sender};
+use futures::future::{self, Either, Future};
+use futures::sync::oneshot::{self, Sender};
 use hashbrown::HashMap;
 use parking_lot::{Mutex, RwLock};
 use regex::{Captures, Regex};
-use reqwest::Method;
-use reqwest::r#async::{Client as ReqwestClient, Response};
+use reqwest::header::HeaderMap;
+use reqwest::{Method, StatusCode};
+use reqwest::r#async::Response;
 use tokio::timer::Delay;
 
+use crate::constants::RELEASE_TIMEOUT;
 use crate::Error;
-use crate::errors::APIError;
+use crate::errors::{APIError, ErrorResponse, RatelimitError};
 
 #[derive(Deserialize, Debug, Clone)]
 struct RatelimitResponse {
@@ -25,157 +28,310 @@ struct RatelimitResponse {
     global: bool,
 }
 
-/// A utility for rate limiting requests made to the Discord API.
-#[derive(Debug)]
-pub struct Ratelimter {
-    /// The underlying client instance.
-    pub http: ReqwestClient,
-    /// A collection of rate limit buckets, mapped by route.
-    pub buckets: Arc<RwLock<HashMap<String, Arc<Mutex<Bucket>>>>>,
-    /// The reset time for the global Discord rate limit.
-    global: Arc<Mutex<Option<DateTime<Utc>>>>,
+/// A backend which keeps track of Discord rate limits, and delays requests which would exceed them.
+pub trait RatelimitBackend: Debug + Send + Sync {
+    /// Reserves a request to the provided route.
+    /// The returned future resolves once the request may be made.
+    fn acquire(&self, route: &str) -> Box<Future<Item=(), Error=Error> + Send>;
+    /// Updates the rate limits of the provided route, using information from a response.
+    fn update(&self, route: &str, info: RatelimitInfo) -> Box<Future<Item=(), Error=Error> + Send>;
 }
 
-pub enum ResponseStatus {
-    Success(Response),
-    Ratelimited,
-    ServerError,
+lazy_static! {
+    static ref ID_REGEX: Regex = Regex::new(r"/([a-z-]+)/\d{16,20}").unwrap();
+    static ref REACTION_REGEX: Regex = Regex::new(r"/reactions/.+$").unwrap();
+    static ref WEBHOOK_REGEX: Regex = Regex::new(r"^/webhooks/(\d+)/[A-Za-z0-9_-]+").unwrap();
+    static ref MAJOR_REGEX: Regex = Regex::new(r"/(channels|guilds|webhooks)/(\d+)").unwrap();
 }
 
-#[derive(Deserialize)]
-struct ErrorResponse {
-    code: i32,
-    message: String,
+/// Creates the storage key of a route's bucket.
+/// Once Discord has reported the route's bucket hash, routes which share the hash and major parameter share a bucket.
+pub fn bucket_key(route: &str, hash: Option<&str>) -> String {
+    match hash {
+        Some(hash) => {
+            let major = MAJOR_REGEX.captures(route)
+                .map(|c| format!("{}:{}", &c[1], &c[2]))
+                .unwrap_or_default();
+            format!("{}:{}", hash, major)
+        },
+        None => route.to_string()
+    }
+}
+
+/// The rate limit information provided by a response from the Discord API.
+#[derive(Debug, Clone, Default)]
+pub struct RatelimitInfo {
+    /// The hash of the bucket which the route belongs to, as reported by Discord.
+    pub bucket: Option<String>,
+    /// The amount of requests which may be made to the route per window.
+    pub limit: Option<i64>,
+    /// The amount of requests remaining in the current window.
+    pub remaining: Option<i64>,
+    /// The time until the route's window resets.
+    pub reset_after: Option<Duration>,
+    /// The time until the global rate limit resets, if it has been reached.
+    pub global: Option<Duration>,
 }
 
+impl RatelimitInfo {
+    /// Parses the rate limit headers of a response.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let reset_after = header::<f64>(headers, "x-ratelimit-reset-after")
+            .map(from_secs)
+            .or_else(|| {
+                // Older responses only provide the reset timestamp, which is measured against the server's date.
+                let date = headers.get("date")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| DateTime::parse_from_str(&v.replace("GMT", "+0000"), "%a, %d %b %Y %T %z").ok())?;
+                let reset = header::<f64>(headers, "x-ratelimit-reset")?;
+
+                Some(from_secs(reset - date.timestamp() as f64))
+            });
+
+        // The global rate limit is only reported by 429 responses, along with the time to wait before retrying.
+        let global = match header::<bool>(headers, "x-ratelimit-global") {
+            Some(true) => Some(header::<f64>(headers, "retry-after").map(from_secs).unwrap_or(Duration::from_secs(1))),
+            _ => None
+        };
 
-impl Ratelimter {
-    pub fn new(http: ReqwestClient) -> Self {
         Self {
-            http,
-            buckets: Arc::new(RwLock::new(HashMap::new())),
-            global: Arc::new(Mutex::new(None)),
+            bucket: header(headers, "x-ratelimit-bucket"),
+            limit: header(headers, "x-ratelimit-limit"),
+            remaining: header(headers, "x-ratelimit-remaining"),
+            reset_after,
+            global
         }
     }
+}
 
-    pub fn enqueue(&mut self, path: String) -> Box<Future<Item=(), Error=Error> + Send> {
-        let buckets = Arc::clone(&self.buckets);
-        let mut routes = buckets.write();
-        let bucket = routes.entry(path.clone())
-            .or_insert(Arc::new(Mutex::new(Bucket::new(path))));
-
-        if self.global.lock().is_some() {
-            let global = self.global.lock().take().unwrap();
-            let duration = global.sub(Utc::now()).to_std().unwrap();
-            warn!("Reached global ratelimit, slowing down request.");
-            Box::new(Delay::new(Instant::now() + duration).map_err(Error::from))
-        } else if bucket.lock().remaining <= 0 {
-            let ready = bucket.lock().take();
-            match ready {
-                Some(_) => {
-                    warn!("Reached route-level ratelimit, slowing down request.");
-                    let bkt = Arc::clone(&bucket);
-                    let reset = bkt.lock().reset.unwrap();
-                    let duration = reset.sub(Utc::now()).to_std().unwrap_or(Duration::from_secs(0));
-                    Box::new(Delay::new(Instant::now() + duration)
-                        .map_err(Error::from)
-                        .map(move |_| {
-                            let mut curr = bkt.lock();
-                            curr.remaining = curr.limit;
-                        })
-                    )
-                }
-
-                None => Box::new(futures::future::ok(()))
-            }
-        } else {
-            Box::new(futures::future::ok(()))
+fn from_secs(secs: f64) -> Duration {
+    Duration::from_millis((secs.max(0.0) * 1000.0).ceil() as u64)
+}
+
+fn header<T: FromStr>(headers: &HeaderMap, name: &str) -> Option<T> {
+    headers.get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+pub enum ResponseStatus {
+    Success(Response),
+    Ratelimited(RatelimitError),
+    ServerError(APIError),
+}
+
+/// Updates the rate limiter, if any, with the provided response, and determines whether the request should be retried.
+pub(crate) fn handle_resp(limiter: Option<Arc<RatelimitBackend>>, route: String, mut resp: Response) -> Box<Future<Item=ResponseStatus, Error=Error> + Send> {
+    let info = RatelimitInfo::from_headers(resp.headers());
+    let global = info.global.is_some();
+    let status = resp.status();
+    let update = move |info: RatelimitInfo| -> Box<Future<Item=(), Error=Error> + Send> {
+        match limiter {
+            Some(limiter) => limiter.update(&route, info),
+            None => Box::new(future::ok(()))
         }
+    };
+
+    if status.is_server_error() {
+        Box::new(resp.json::<ErrorResponse>().then(move |body| {
+            let error = APIError::new(status, body.ok());
+            update(info).map(move |_| ResponseStatus::ServerError(error))
+        }))
+    } else if status == StatusCode::TOO_MANY_REQUESTS {
+        Box::new(resp.json::<RatelimitResponse>().then(move |body| {
+            let body = body.ok();
+            // Proxies may answer with their own body, in which case the headers are relied upon.
+            let retry_after = body.as_ref()
+                .map(|b| Duration::from_millis(b.retry_after))
+                .or(info.reset_after)
+                .unwrap_or(Duration::from_secs(1));
+            let error = RatelimitError {
+                retry_after,
+                global: global || body.map_or(false, |b| b.global),
+                bucket: info.bucket.clone(),
+            };
+            let info = if error.global {
+                warn!("Reached global ratelimit, retrying in {:?}.", retry_after);
+                RatelimitInfo { global: Some(retry_after), ..info }
+            } else {
+                RatelimitInfo { remaining: Some(0), reset_after: Some(retry_after), ..info }
+            };
+
+            update(info).map(move |_| ResponseStatus::Ratelimited(error))
+        }))
+    } else if status == StatusCode::UNAUTHORIZED {
+        Box::new(update(info).and_then(|_| future::err(Error::InvalidTokenError)))
+    } else if status.is_client_error() {
+        let error = resp.json::<ErrorResponse>().then(move |body| {
+            future::err(Error::Discord(APIError::new(status, body.ok())))
+        });
+        Box::new(update(info).and_then(|_| error))
+    } else {
+        Box::new(update(info).map(move |_| ResponseStatus::Success(resp)))
     }
+}
 
-    pub(crate) fn handle_resp(&mut self, path: String, mut resp: Response) -> Box<Future<Item=ResponseStatus, Error=Error> + Send> {
-        let buckets = Arc::clone(&self.buckets);
-        let mut routes = buckets.write();
-        let bucket = routes.entry(path.clone())
-            .or_insert(Arc::new(Mutex::new(Bucket::new(path))));
+/// An in-memory rate limiter, which keeps track of rate limits within a single process.
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryRatelimiter {
+    /// A collection of rate limit buckets, mapped by bucket key.
+    pub buckets: Arc<RwLock<HashMap<String, Arc<Mutex<Bucket>>>>>,
+    /// The bucket hashes reported by Discord, mapped by route.
+    routes: Arc<RwLock<HashMap<String, String>>>,
+    /// The reset time for the global Discord rate limit.
+    global: Arc<Mutex<Option<DateTime<Utc>>>>,
+}
 
-        let headers = resp.headers();
-        if headers.contains_key("x-ratelimit-limit") {
-            (*bucket.lock()).limit = headers["x-ratelimit-limit"].to_str()
-                .unwrap()
-                .parse::<i64>()
-                .expect("Failed to parse ratelimit limit header")
+impl InMemoryRatelimiter {
+    /// Creates a new in-memory rate limiter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn bucket(&self, route: &str) -> Arc<Mutex<Bucket>> {
+        let key = bucket_key(route, self.routes.read().get(route).map(String::as_str));
+        let mut buckets = self.buckets.write();
+        let bucket = buckets.entry(key)
+            .or_insert_with(|| Arc::new(Mutex::new(Bucket::new(route.to_string()))));
+
+        Arc::clone(bucket)
+    }
+
+    /// Moves a route's bucket under the bucket hash which Discord has reported for it, so that requests queued before the hash was known are not orphaned.
+    /// If another route already uses the hashed bucket, the route's queued requests and window are merged into it.
+    fn migrate(&self, route: &str, hash: &str) {
+        let mut buckets = self.buckets.write();
+        let old = match buckets.remove(route) {
+            Some(old) => old,
+            None => return
         };
-        if headers.contains_key("x-ratelimit-remaining") {
-            (*bucket.lock()).remaining = headers["x-ratelimit-remaining"].to_str()
-                .unwrap()
-                .parse::<i64>()
-                .expect("Failed to parse ratelimit remaining header")
+        let key = bucket_key(route, Some(hash));
+        let new = match buckets.get(&key) {
+            Some(new) => Arc::clone(new),
+            None => {
+                buckets.insert(key, old);
+                return;
+            }
         };
+        drop(buckets);
 
-        let status = resp.status();
-        if status.is_server_error() {
-            Box::new(Delay::new(Instant::now() + Duration::from_secs(5))
-                .map_err(Error::from)
-                .map(|_| ResponseStatus::ServerError)
-            )
-        } else if status.as_u16() == 429 {
-            let global = Arc::clone(&self.global);
-            let bkt = Arc::clone(&bucket);
-            Box::new(resp.json::<RatelimitResponse>().from_err().map(move |body| {
-                let duration = chrono::Duration::from_std(Duration::from_millis(body.retry_after)).unwrap();
-                let reset = Utc::now()
-                    .checked_add_signed(duration);
-                if body.global {
-                    *global.lock() = reset;
-                } else {
-                    (*bkt.lock()).reset = reset
-                };
-
-                ResponseStatus::Ratelimited
-            }))
-        } else if status.is_client_error() {
-            Box::new(resp.json::<ErrorResponse>().from_err()
-                .and_then(move |body| {
-                    futures::future::err(Error::Discord(APIError {
-                        code: body.code,
-                        message: body.message,
-                        http_status: resp.status(),
-                    }))
-                })
-            )
-        } else {
-            let bucket = Arc::clone(&bucket);
-            if headers.contains_key("x-ratelimit-reset") {
-                let reset_time = headers["x-ratelimit-reset"]
-                    .to_str()
-                    .unwrap()
-                    .parse::<i64>()
-                    .expect("Failed to parse ratelimit reset header");
-                let date = headers["date"].to_str()
-                    .unwrap()
-                    .replace("GMT", "+0000");
-                let parsed = DateTime::parse_from_str(&date, "%a, %d %b %Y %T %z")
-                    .unwrap()
-                    .timestamp();
-                let current = Utc::now().timestamp();
-                let diff = current - parsed;
-                (*bucket.lock()).reset = Some(Utc.timestamp(reset_time + diff, 0));
-
-                Box::new(futures::future::ok(ResponseStatus::Success(resp)))
-            } else {
-                Box::new(futures::future::ok(ResponseStatus::Success(resp)))
-            }
-        }
+        let mut old = old.lock();
+        let mut state = new.lock();
+        state.queue.extend(old.queue.drain(..));
+        state.remaining = cmp::min(state.remaining, old.remaining);
+        state.reset = cmp::max(state.reset, old.reset);
+        schedule(&new, &mut state);
     }
 }
 
+impl RatelimitBackend for InMemoryRatelimiter {
+    fn acquire(&self, route: &str) -> Box<Future<Item=(), Error=Error> + Send> {
+        let bucket = self.bucket(route);
+        let global = self.global.lock()
+            .and_then(|reset| reset.sub(Utc::now()).to_std().ok());
+        let global = match global {
+            Some(duration) => {
+                warn!("Reached global ratelimit, slowing down request.");
+                Either::A(Delay::new(Instant::now() + duration).map_err(Error::from))
+            },
+            None => Either::B(future::ok(()))
+        };
+
+        Box::new(global.and_then(move |_| {
+            let mut state = bucket.lock();
+            state.refresh();
+            if state.queue.is_empty() && state.remaining > 0 {
+                state.remaining -= 1;
+                return Either::A(future::ok(()));
+            };
+
+            debug!("Reached route-level ratelimit for {}, queueing request.", state.route);
+            let (tx, rx) = oneshot::channel();
+            state.queue.push_back(tx);
+            schedule(&bucket, &mut state);
+
+            // The sender is only dropped along with the bucket, in which case the request is let through.
+            Either::B(rx.then(|_| Ok(())))
+        }))
+    }
+
+    fn update(&self, route: &str, info: RatelimitInfo) -> Box<Future<Item=(), Error=Error> + Send> {
+        let after = |d: Duration| chrono::Duration::from_std(d).ok()
+            .and_then(|d| Utc::now().checked_add_signed(d));
+        if let Some(global) = info.global {
+            *self.global.lock() = after(global);
+        };
+        if let Some(hash) = info.bucket {
+            let previous = self.routes.write().insert(route.to_string(), hash.clone());
+            if previous.is_none() {
+                self.migrate(route, &hash);
+            };
+        };
+
+        let bucket = self.bucket(route);
+        let mut state = bucket.lock();
+        state.refresh();
+        if let Some(limit) = info.limit {
+            state.limit = limit;
+        };
+        if let Some(remaining) = info.remaining {
+            // Within a window, responses to requests that were released together may arrive in any order.
+            state.remaining = match state.reset {
+                Some(_) => cmp::min(state.remaining, remaining),
+                None => remaining
+            };
+        };
+        if let Some(reset_after) = info.reset_after {
+            state.reset = after(reset_after);
+        };
+        state.release();
+        schedule(&bucket, &mut state);
+
+        Box::new(future::ok(()))
+    }
+}
+
+/// Schedules the release of a bucket's queued requests once the bucket resets.
+/// If the reset is unknown, such as when the response to the last request never arrived, a single request is released after a timeout.
+/// A release which was scheduled with the timeout is replaced once the reset becomes known.
+fn schedule(bucket: &Arc<Mutex<Bucket>>, state: &mut Bucket) {
+    if state.queue.is_empty() {
+        return;
+    };
+    let wait = state.reset
+        .and_then(|reset| reset.sub(Utc::now()).to_std().ok())
+        .unwrap_or(RELEASE_TIMEOUT);
+    let at = Instant::now() + wait;
+    match state.scheduled {
+        Some(scheduled) if scheduled <= at => return,
+        _ => state.scheduled = Some(at)
+    };
+
+    let bucket = Arc::clone(bucket);
+    tokio::spawn(Delay::new(at).then(move |_| {
+        let mut state = bucket.lock();
+        // A release which was replaced by an earlier one is left to that release.
+        if state.scheduled != Some(at) {
+            return Ok(());
+        };
+        state.scheduled = None;
+        state.refresh();
+        if state.reset.is_none() && state.remaining <= 0 {
+            state.remaining = 1;
+        };
+        state.release();
+        schedule(&bucket, &mut state);
+
+        Ok(())
+    }));
+}
+
 /// A rate limiter bucket used for maintaining Discord rate limits.
 #[derive(Debug)]
 pub struct Bucket {
-    /// A queue of requests that are being ratelimited.
+    /// A queue of requests that are being ratelimited, which are released in order.
     pub queue: VecDeque<Sender<()>>,
-    /// The remaining time left for this request.
+    /// The remaining amount of requests in the current window.
     pub remaining: i64,
     /// The route that this bucket is for.
     pub route: String,
@@ -183,45 +339,45 @@ pub struct Bucket {
     pub limit: i64,
     /// The time in which the ratelimit resets.
     pub reset: Option<DateTime<Utc>>,
+    /// The time at which the release of queued requests has been scheduled, if it has been.
+    scheduled: Option<Instant>,
 }
 
 impl Bucket {
-    /// Creates a bucket route to be used as a storage key.
-    #[allow(unused_assignments)]
-    pub fn make_route(method: Method, path: String) -> String {
-        let default_regex = Regex::new(r"/([a-z-]+)/(?:[0-9]{17,19})/g").unwrap();
-        let reaction_regex = Regex::new(r"/reactions/[^/]+/g").unwrap();
-        let webhook_regex = Regex::new(r"^/webhooks/(\d+)/[A-Za-z0-9-_]{64,}/").unwrap();
-
-        let mut route = default_regex.replace(&path, |matches: &Captures| {
-            let mat = matches.get(1).unwrap();
-            match mat.as_str() {
-                "channels" | "guilds" | "webhooks" => mat.as_str().to_owned(),
-                _ => format!("/{}/:id", mat.as_str())
-            }
+    /// Normalises a request path into a route, which identifies the request until Discord reports its bucket.
+    /// IDs are replaced with placeholders, except for major parameters, which have their own rate limits.
+    pub fn make_route(method: &Method, path: &str) -> String {
+        let path = path.split('?').next().unwrap_or(path);
+        let route = WEBHOOK_REGEX.replace(path, "/webhooks/$1/:token");
+        let route = REACTION_REGEX.replace(&route, "/reactions/:reaction");
+        let route = ID_REGEX.replace_all(&route, |caps: &Captures| match &caps[1] {
+            "channels" | "guilds" | "webhooks" => caps[0].to_string(),
+            resource => format!("/{}/:id", resource)
         });
 
-        let owned = route.into_owned();
-        route = reaction_regex.replace(owned.as_str(), "/reactions/:id");
-        route = webhook_regex.replace(owned.as_str(), "/webhooks/$1/:token");
-
-        if method == Method::DELETE && route.ends_with("/messages/:id") {
-            let formatted = format!("{}{}", method.as_str(), route);
-            route = Cow::from(formatted);
-        }
-
-        route.into_owned()
+        format!("{} {}", method.as_str(), route)
     }
 
-    fn take(&mut self) -> Option<Receiver<()>> {
-        if self.reset.is_some() {
-            let (tx, rx) = oneshot::channel();
-
-            self.queue.push_back(tx);
+    /// Starts a new window if the bucket has reset.
+    fn refresh(&mut self) {
+        if let Some(reset) = self.reset {
+            if Utc::now() >= reset {
+                self.remaining = self.limit;
+                self.reset = None;
+            };
+        };
+    }
 
-            Some(rx)
-        } else {
-            None
+    /// Releases queued requests in order, while the bucket has requests remaining.
+    fn release(&mut self) {
+        while self.remaining > 0 {
+            match self.queue.pop_front() {
+                // Requests which were dropped while queued do not use up the window.
+                Some(tx) => if tx.send(()).is_ok() {
+                    self.remaining -= 1;
+                },
+                None => break
+            };
         }
     }
 
@@ -232,6 +388,124 @@ impl Bucket {
             limit: 1,
             remaining: 1,
             reset: None,
+            scheduled: None,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{future, Future};
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use reqwest::Method;
+    use tokio::runtime::current_thread::Runtime;
+    use tokio::timer::Timeout;
+
+    use super::{Bucket, InMemoryRatelimiter, RatelimitBackend, RatelimitInfo};
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for &(name, value) in pairs {
+            headers.insert(name, HeaderValue::from_static(value));
+        }
+
+        headers
+    }
+
+    #[test]
+    fn route_hides_webhook_token() {
+        assert_eq!(
+            Bucket::make_route(&Method::POST, "/webhooks/223704706495545344/3d89bb7572e0fb30d8128367b3b1b44fecd1726de135cbe28a41f8b2f777c372ba2939e72279b94526ff5d1bd4358d65cf11?wait=true"),
+            "POST /webhooks/223704706495545344/:token"
+        );
+    }
+
+    #[test]
+    fn route_groups_reactions() {
+        assert_eq!(
+            Bucket::make_route(&Method::PUT, "/channels/223704706495545344/messages/419474406294798336/reactions/%F0%9F%91%8D/@me"),
+            "PUT /channels/223704706495545344/messages/:id/reactions/:reaction"
+        );
+    }
+
+    #[test]
+    fn route_keeps_major_parameters() {
+        assert_eq!(
+            Bucket::make_route(&Method::GET, "/guilds/223704706495545344/members/419474406294798336"),
+            "GET /guilds/223704706495545344/members/:id"
+        );
+        assert_ne!(
+            Bucket::make_route(&Method::GET, "/channels/223704706495545344/messages"),
+            Bucket::make_route(&Method::GET, "/channels/419474406294798336/messages")
+        );
+    }
+
+    #[test]
+    fn route_ignores_query_string() {
+        assert_eq!(
+            Bucket::make_route(&Method::GET, "/channels/223704706495545344/messages?limit=50&before=419474406294798336"),
+            "GET /channels/223704706495545344/messages"
+        );
+    }
+
+    #[test]
+    fn parses_reset_after() {
+        let info = RatelimitInfo::from_headers(&headers(&[
+            ("x-ratelimit-bucket", "abcd1234"),
+            ("x-ratelimit-limit", "5"),
+            ("x-ratelimit-remaining", "4"),
+            ("x-ratelimit-reset-after", "1.250"),
+        ]));
+        assert_eq!(info.bucket, Some(String::from("abcd1234")));
+        assert_eq!(info.limit, Some(5));
+        assert_eq!(info.remaining, Some(4));
+        assert_eq!(info.reset_after, Some(Duration::from_millis(1250)));
+        assert_eq!(info.global, None);
+    }
+
+    #[test]
+    fn falls_back_to_reset_and_date() {
+        let info = RatelimitInfo::from_headers(&headers(&[
+            ("date", "Wed, 21 Oct 2015 07:28:00 GMT"),
+            ("x-ratelimit-reset", "1445412482.5"),
+        ]));
+        assert_eq!(info.reset_after, Some(Duration::from_millis(2500)));
+    }
+
+    #[test]
+    fn parses_global_limit() {
+        let info = RatelimitInfo::from_headers(&headers(&[
+            ("x-ratelimit-global", "true"),
+            ("retry-after", "3"),
+        ]));
+        assert_eq!(info.global, Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn queued_requests_follow_bucket_hash() {
+        let mut rt = Runtime::new().unwrap();
+        let limiter = InMemoryRatelimiter::new();
+        let route = "GET /channels/223704706495545344/messages";
+
+        rt.block_on(limiter.acquire(route)).unwrap();
+        // The route-keyed bucket only allows one request until a response has been received.
+        let queued = rt.block_on(future::lazy(|| {
+            let mut queued = limiter.acquire(route);
+            assert!(queued.poll().unwrap().is_not_ready());
+            Ok::<_, ()>(queued)
+        })).unwrap();
+        rt.block_on(limiter.update(route, RatelimitInfo {
+            bucket: Some(String::from("abcd1234")),
+            limit: Some(5),
+            remaining: Some(4),
+            reset_after: Some(Duration::from_secs(60)),
+            global: None
+        })).unwrap();
+
+        assert!(rt.block_on(Timeout::new(queued, Duration::from_secs(1))).is_ok());
+        assert!(limiter.buckets.read().get(route).is_none());
+        assert_eq!(limiter.buckets.read().len(), 1);
+    }
+}
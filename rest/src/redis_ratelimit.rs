@@ -0,0 +1,283 @@
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::time::{Duration, Instant};
+
+use futures::future::{self, Future, Loop};
+use redis::r#async::SharedConnection;
+use tokio::timer::Delay;
+
+use crate::constants::RELEASE_TIMEOUT;
+use crate::Error;
+use crate::ratelimit::{bucket_key, RatelimitBackend, RatelimitInfo};
+
+/// Reserves a request to a route, returning the amount of milliseconds to wait if the route or the global limit is exhausted.
+/// If an exhausted route's reset is unknown, the route expires after the provided timeout instead.
+const ACQUIRE_SCRIPT: &str = r"
+local global = redis.call('PTTL', KEYS[1])
+if global > 0 then
+    return global
+end
+local remaining = tonumber(redis.call('HGET', KEYS[2], 'remaining'))
+if remaining then
+    if remaining <= 0 then
+        local reset = redis.call('PTTL', KEYS[2])
+        if reset > 0 then
+            return reset
+        end
+        redis.call('PEXPIRE', KEYS[2], ARGV[1])
+        return tonumber(ARGV[1])
+    end
+    redis.call('HINCRBY', KEYS[2], 'remaining', -1)
+end
+return 0
+";
+
+/// Updates a route's bucket and the global limit, and records the route's bucket hash if one was provided.
+/// When a route's hash is first recorded, the bucket kept under the route itself is moved to the hashed bucket, or merged into it if another route already uses it.
+/// Negative arguments are treated as missing.
+const UPDATE_SCRIPT: &str = r"
+if ARGV[5] ~= '' then
+    redis.call('SET', KEYS[3], ARGV[5])
+    if KEYS[4] ~= KEYS[2] and redis.call('EXISTS', KEYS[4]) == 1 then
+        if redis.call('EXISTS', KEYS[2]) == 0 then
+            redis.call('RENAME', KEYS[4], KEYS[2])
+        else
+            local old = tonumber(redis.call('HGET', KEYS[4], 'remaining'))
+            local current = tonumber(redis.call('HGET', KEYS[2], 'remaining'))
+            if old and (not current or old < current) then
+                redis.call('HSET', KEYS[2], 'remaining', old)
+            end
+            local old_reset = redis.call('PTTL', KEYS[4])
+            if old_reset > redis.call('PTTL', KEYS[2]) then
+                redis.call('PEXPIRE', KEYS[2], old_reset)
+            end
+            redis.call('DEL', KEYS[4])
+        end
+    end
+end
+local limit = tonumber(ARGV[1])
+local remaining = tonumber(ARGV[2])
+local reset_after = tonumber(ARGV[3])
+local global = tonumber(ARGV[4])
+if global > 0 then
+    redis.call('SET', KEYS[1], 1, 'PX', global)
+end
+if limit >= 0 then
+    redis.call('HSET', KEYS[2], 'limit', limit)
+end
+if remaining >= 0 then
+    local current = tonumber(redis.call('HGET', KEYS[2], 'remaining'))
+    if not current or remaining < current then
+        redis.call('HSET', KEYS[2], 'remaining', remaining)
+    end
+end
+if reset_after > 0 then
+    redis.call('PEXPIRE', KEYS[2], reset_after)
+end
+return 0
+";
+
+/// A rate limiter which keeps buckets and the global limit in Redis, so that rate limits are shared between processes.
+///
+/// Every bucket is stored in a hash which expires when the bucket resets, and each update is applied atomically with a Lua script.
+#[derive(Clone)]
+pub struct RedisRatelimiter {
+    /// The underlying Redis connection.
+    pub conn: SharedConnection,
+    prefix: String,
+}
+
+impl RedisRatelimiter {
+    /// Creates a new Redis rate limiter with the provided connection, which stores its keys under the provided prefix.
+    pub fn new(conn: SharedConnection, prefix: &str) -> Self {
+        Self {
+            conn,
+            prefix: prefix.to_string(),
+        }
+    }
+
+    fn global_key(&self) -> String {
+        format!("{}:global", self.prefix)
+    }
+
+    fn route_key(&self, route: &str) -> String {
+        format!("{}:route:{}", self.prefix, route)
+    }
+
+    /// Resolves the key of a route's bucket, using the bucket hash which Discord reported for the route, if any.
+    fn bucket_key(&self, route: &str) -> impl Future<Item=String, Error=Error> + Send {
+        let prefix = self.prefix.clone();
+        let route = route.to_string();
+
+        redis::cmd("GET").arg(self.route_key(&route))
+            .query_async::<_, Option<String>>(self.conn.clone())
+            .from_err()
+            .map(move |(_, hash)| format!("{}:bucket:{}", prefix, bucket_key(&route, hash.as_ref().map(String::as_str))))
+    }
+}
+
+impl Debug for RedisRatelimiter {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("RedisRatelimiter")
+            .field("prefix", &self.prefix)
+            .finish()
+    }
+}
+
+impl RatelimitBackend for RedisRatelimiter {
+    fn acquire(&self, route: &str) -> Box<Future<Item=(), Error=Error> + Send> {
+        let conn = self.conn.clone();
+        let global = self.global_key();
+        let timeout = RELEASE_TIMEOUT.as_secs() * 1000;
+
+        Box::new(self.bucket_key(route).and_then(move |bucket| future::loop_fn((), move |_| {
+            redis::cmd("EVAL").arg(ACQUIRE_SCRIPT).arg(2).arg(&global).arg(&bucket).arg(timeout)
+                .query_async::<_, i64>(conn.clone())
+                .from_err()
+                .and_then(|(_, wait)| -> Box<Future<Item=Loop<(), ()>, Error=Error> + Send> {
+                    if wait <= 0 {
+                        return Box::new(future::ok(Loop::Break(())));
+                    };
+                    warn!("Reached shared ratelimit, slowing down request for {}ms.", wait);
+                    Box::new(Delay::new(Instant::now() + Duration::from_millis(wait as u64))
+                        .from_err()
+                        .map(|_| Loop::Continue(()))
+                    )
+                })
+        })))
+    }
+
+    fn update(&self, route: &str, info: RatelimitInfo) -> Box<Future<Item=(), Error=Error> + Send> {
+        let conn = self.conn.clone();
+        let global = self.global_key();
+        let route_key = self.route_key(route);
+        let route_bucket = format!("{}:bucket:{}", self.prefix, bucket_key(route, None));
+        // A newly reported bucket hash takes effect immediately, rather than after it has been stored.
+        let bucket: Box<Future<Item=String, Error=Error> + Send> = match info.bucket {
+            Some(ref hash) => Box::new(future::ok(format!("{}:bucket:{}", self.prefix, bucket_key(route, Some(hash))))),
+            None => Box::new(self.bucket_key(route))
+        };
+
+        Box::new(bucket.and_then(move |bucket| {
+            redis::cmd("EVAL").arg(UPDATE_SCRIPT).arg(4)
+                .arg(global)
+                .arg(bucket)
+                .arg(route_key)
+                .arg(route_bucket)
+                .arg(info.limit.unwrap_or(-1))
+                .arg(info.remaining.unwrap_or(-1))
+                .arg(millis(info.reset_after))
+                .arg(millis(info.global))
+                .arg(info.bucket.unwrap_or_default())
+                .query_async::<_, ()>(conn)
+                .from_err()
+                .map(|_| ())
+        }))
+    }
+}
+
+fn millis(duration: Option<Duration>) -> i64 {
+    duration.map_or(-1, |d| (d.as_secs() * 1000 + u64::from(d.subsec_millis())) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    use redis::Client;
+    use tokio::runtime::current_thread::Runtime;
+
+    use crate::constants::RELEASE_TIMEOUT;
+    use crate::ratelimit::{RatelimitBackend, RatelimitInfo};
+
+    use super::RedisRatelimiter;
+
+    const ROUTE: &str = "GET /channels/223704706495545344/messages";
+
+    /// Connects to a Redis server on localhost, using a key prefix which is unique to the test.
+    fn connect(rt: &mut Runtime) -> RedisRatelimiter {
+        let client = Client::open("redis://127.0.0.1/").unwrap();
+        let conn = rt.block_on(client.get_shared_async_connection()).unwrap();
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().subsec_nanos();
+
+        RedisRatelimiter::new(conn, &format!("spectacles-test:ratelimit:{}", nanos))
+    }
+
+    /// Measures how long an acquire for the provided route waits.
+    fn acquire(rt: &mut Runtime, limiter: &RedisRatelimiter, route: &str) -> Duration {
+        let start = Instant::now();
+        rt.block_on(limiter.acquire(route)).unwrap();
+
+        start.elapsed()
+    }
+
+    /// Requires a Redis server on localhost, run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn exhausted_bucket_waits_for_reset() {
+        let mut rt = Runtime::new().unwrap();
+        let limiter = connect(&mut rt);
+        rt.block_on(limiter.update(ROUTE, RatelimitInfo {
+            bucket: Some(String::from("abcd1234")),
+            limit: Some(5),
+            remaining: Some(0),
+            reset_after: Some(Duration::from_millis(1500)),
+            global: None
+        })).unwrap();
+
+        let waited = acquire(&mut rt, &limiter, ROUTE);
+        assert!(waited >= Duration::from_millis(1300), "Waited {:?}", waited);
+        assert!(waited < Duration::from_millis(2500), "Waited {:?}", waited);
+    }
+
+    /// Requires a Redis server on localhost, run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn global_limit_blocks_every_route() {
+        let mut rt = Runtime::new().unwrap();
+        let limiter = connect(&mut rt);
+        rt.block_on(limiter.update(ROUTE, RatelimitInfo {
+            global: Some(Duration::from_millis(1500)),
+            ..RatelimitInfo::default()
+        })).unwrap();
+
+        let waited = acquire(&mut rt, &limiter, "POST /guilds/419474406294798336/channels");
+        assert!(waited >= Duration::from_millis(1300), "Waited {:?}", waited);
+    }
+
+    /// Requires a Redis server on localhost, run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn unknown_reset_waits_for_timeout() {
+        let mut rt = Runtime::new().unwrap();
+        let limiter = connect(&mut rt);
+        rt.block_on(limiter.update(ROUTE, RatelimitInfo {
+            remaining: Some(0),
+            ..RatelimitInfo::default()
+        })).unwrap();
+
+        let waited = acquire(&mut rt, &limiter, ROUTE);
+        assert!(waited >= RELEASE_TIMEOUT - Duration::from_millis(200), "Waited {:?}", waited);
+    }
+
+    /// Requires a Redis server on localhost, run with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn route_bucket_moves_to_reported_hash() {
+        let mut rt = Runtime::new().unwrap();
+        let limiter = connect(&mut rt);
+        rt.block_on(limiter.update(ROUTE, RatelimitInfo {
+            remaining: Some(0),
+            reset_after: Some(Duration::from_millis(1500)),
+            ..RatelimitInfo::default()
+        })).unwrap();
+        rt.block_on(limiter.update(ROUTE, RatelimitInfo {
+            bucket: Some(String::from("abcd1234")),
+            limit: Some(5),
+            ..RatelimitInfo::default()
+        })).unwrap();
+
+        let waited = acquire(&mut rt, &limiter, ROUTE);
+        assert!(waited >= Duration::from_millis(1300), "Waited {:?}", waited);
+        assert!(waited < Duration::from_millis(2500), "Waited {:?}", waited);
+    }
+}
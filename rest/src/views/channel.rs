@@ -1,6 +1,5 @@
 use futures::future::Future;
 use reqwest::Method;
-use reqwest::r#async::multipart::{Form, Part};
 
 use spectacles_model::channel::{Channel, ModifyChannelOptions};
 use spectacles_model::invite::{CreateInviteOptions, Invite};
@@ -37,16 +36,12 @@ impl ChannelView {
             format!("/channels/{}/messages", self.id),
         );
         let create = payload.as_message();
-        let json = serde_json::to_string(&create).expect("Failed to serialize message");
+        let endpt = endpt.json(&create);
 
         if let Some((name, file)) = create.file {
-            self.client.request(endpt.multipart(
-                Form::new()
-                    .part("file", Part::bytes(file).file_name(name))
-                    .part("payload_json", Part::text(json))
-            ))
+            self.client.request(endpt.file("file", name, file))
         } else {
-            self.client.request(endpt.json(create))
+            self.client.request(endpt)
         }
     }
 
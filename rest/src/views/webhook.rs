@@ -1,6 +1,5 @@
 use futures::Future;
 use reqwest::Method;
-use reqwest::r#async::multipart::{Form, Part};
 
 use spectacles_model::message::{ExecuteWebhookOptions, Message, ModifyWebhookOptions, Webhook};
 
@@ -69,17 +68,11 @@ impl WebhookView {
     /// Executes the provided webhook, with the provided options.
     pub fn execute(&self, token: &str, opts: ExecuteWebhookOptions, wait: bool) -> impl Future<Item=Option<Message>, Error=Error> {
         let endpt = Endpoint::new(Method::POST, format!("/webhooks/{}/{}", self.id, token));
-        let json = serde_json::to_string(&opts).expect("Failed to serialize webhook message");
+        let endpt = endpt.json(&opts).query(json!({ "wait": wait }));
         if let Some((name, file)) = opts.file {
-            self.client.request(endpt.multipart(
-                Form::new()
-                    .part("file", Part::bytes(file).file_name(name))
-                    .part("payload_json", Part::text(json))
-            ).query(json!({ "wait": wait })))
+            self.client.request(endpt.file("file", name, file))
         } else {
-            self.client.request(endpt.json(opts).query(json!({
-                "wait": wait
-            })))
+            self.client.request(endpt)
         }
     }
 }
\ No newline at end of file